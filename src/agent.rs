@@ -0,0 +1,271 @@
+use crate::{FallingPiece, FallingPieceAction, Grid, GridSide, ReachablePlacements};
+use bevy::prelude::*;
+
+/// Something that can play the game, given the grids and the falling piece.
+pub trait Agent: Send + Sync {
+    /// Picks where the falling piece should go, usually out of `ReachablePlacements`. `None` means the agent has
+    /// nothing better than dropping the piece where it is.
+    fn choose_placement(
+        &mut self,
+        left: &Grid,
+        right: &Grid,
+        piece: &FallingPiece,
+    ) -> Option<Placement>;
+}
+
+/// Where to put the falling piece, as the actions that get it there, ending with a fast drop.
+#[derive(Clone, Debug, Default)]
+pub struct Placement {
+    pub path: Vec<FallingPieceAction>,
+}
+
+/// Which cells of a grid are filled, without anything else a `Grid` keeps track of. Cheap to copy and change while
+/// trying out placements.
+#[derive(Clone, Debug)]
+pub struct Shadow {
+    width: i32,
+    height: i32,
+    filled: Vec<bool>,
+}
+
+impl Shadow {
+    pub fn from_grid(grid: &Grid) -> Self {
+        let (width, height) = (grid.width(), grid.height());
+        let filled = (0..height)
+            .flat_map(|y| (0..width).map(move |x| IVec2::new(x, y)))
+            .map(|p| grid.is_filled(p))
+            .collect();
+
+        Self {
+            width,
+            height,
+            filled,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn is_filled(&self, p: IVec2) -> bool {
+        self.filled[self.index(p)]
+    }
+
+    pub fn fill(&mut self, cells: &[IVec2]) {
+        for p in cells.iter().cloned() {
+            let i = self.index(p);
+            self.filled[i] = true;
+        }
+    }
+
+    /// Removes full rows the way the game does, and returns how many there were.
+    pub fn clear_full_rows(&mut self) -> u32 {
+        let width = self.width as usize;
+        let rows_before = self.filled.len() / width;
+        let mut kept: Vec<bool> = self
+            .filled
+            .chunks(width)
+            .filter(|row| !row.iter().all(|f| *f))
+            .flatten()
+            .cloned()
+            .collect();
+        let cleared = rows_before - kept.len() / width;
+        kept.resize(self.filled.len(), false);
+        self.filled = kept;
+
+        cleared as u32
+    }
+
+    /// The height of the highest filled cell in each column.
+    pub fn column_heights(&self) -> Vec<i32> {
+        (0..self.width)
+            .map(|x| {
+                (0..self.height)
+                    .rev()
+                    .find(|y| self.is_filled(IVec2::new(x, *y)))
+                    .map_or(0, |y| y + 1)
+            })
+            .collect()
+    }
+
+    /// Empty cells with a filled cell somewhere above them.
+    pub fn holes(&self) -> u32 {
+        let heights = self.column_heights();
+        (0..self.width)
+            .map(|x| {
+                (0..heights[x as usize])
+                    .filter(|y| !self.is_filled(IVec2::new(x, *y)))
+                    .count() as u32
+            })
+            .sum()
+    }
+
+    /// How much the heights of neighbouring columns differ.
+    pub fn bumpiness(&self) -> u32 {
+        self.column_heights()
+            .windows(2)
+            .map(|pair| (pair[0] - pair[1]).unsigned_abs())
+            .sum()
+    }
+
+    fn index(&self, p: IVec2) -> usize {
+        (self.width * p.y + p.x) as usize
+    }
+}
+
+/// One grid after a piece has locked in it and full rows have been cleared.
+#[derive(Clone, Debug)]
+pub struct ShadowOutcome {
+    pub shadow: Shadow,
+    pub lines_cleared: u32,
+}
+
+impl ShadowOutcome {
+    /// Fills in the cells where a piece locked, then clears full rows.
+    pub fn lock_piece(shadow: &Shadow, cells: &[IVec2]) -> Self {
+        let mut shadow = shadow.clone();
+        shadow.fill(cells);
+        let lines_cleared = shadow.clear_full_rows();
+
+        Self {
+            shadow,
+            lines_cleared,
+        }
+    }
+}
+
+/// Both grids after a placement.
+#[derive(Clone, Copy, Debug)]
+pub struct PlacementOutcome<'a> {
+    pub left: &'a ShadowOutcome,
+    pub right: &'a ShadowOutcome,
+}
+
+impl PlacementOutcome<'_> {
+    pub fn is_dual_clear(&self) -> bool {
+        self.left.lines_cleared > 0 && self.right.lines_cleared > 0
+    }
+}
+
+/// Scores the outcome of a placement for `HeuristicAgent`. Higher is better.
+pub trait Evaluator: Send + Sync {
+    /// Scores one grid on its own. Each grid outcome is only scored once, however many placements share it.
+    fn evaluate_grid(&self, outcome: &ShadowOutcome) -> f32;
+
+    /// Scores both grids together, given their scores from `evaluate_grid`. This runs for every placement, so it
+    /// should be cheap.
+    fn evaluate(&self, grid_scores: [f32; 2], _outcome: PlacementOutcome) -> f32 {
+        grid_scores[0] + grid_scores[1]
+    }
+}
+
+/// A weighted sum of the usual Tetris heuristics for each grid, plus a bonus for clearing rows in both grids at once,
+/// which scores double.
+#[derive(Clone, Debug)]
+pub struct HeuristicEvaluator {
+    pub lines_cleared: f32,
+    pub aggregate_height: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub dual_clear: f32,
+}
+
+impl Default for HeuristicEvaluator {
+    fn default() -> Self {
+        Self {
+            lines_cleared: 0.76,
+            aggregate_height: -0.51,
+            holes: -0.36,
+            bumpiness: -0.18,
+            dual_clear: 1.0,
+        }
+    }
+}
+
+impl Evaluator for HeuristicEvaluator {
+    fn evaluate_grid(&self, outcome: &ShadowOutcome) -> f32 {
+        let shadow = &outcome.shadow;
+        let aggregate_height: i32 = shadow.column_heights().iter().sum();
+
+        self.lines_cleared * outcome.lines_cleared as f32
+            + self.aggregate_height * aggregate_height as f32
+            + self.holes * shadow.holes() as f32
+            + self.bumpiness * shadow.bumpiness() as f32
+    }
+
+    fn evaluate(&self, grid_scores: [f32; 2], outcome: PlacementOutcome) -> f32 {
+        let dual_clear = if outcome.is_dual_clear() {
+            self.dual_clear
+        } else {
+            0.0
+        };
+
+        grid_scores[0] + grid_scores[1] + dual_clear
+    }
+}
+
+/// Tries every placement the piece can reach, and picks the one the evaluator likes best.
+pub struct HeuristicAgent {
+    evaluator: Box<dyn Evaluator>,
+}
+
+impl Default for HeuristicAgent {
+    fn default() -> Self {
+        Self::new(Box::new(HeuristicEvaluator::default()))
+    }
+}
+
+impl HeuristicAgent {
+    pub fn new(evaluator: Box<dyn Evaluator>) -> Self {
+        Self { evaluator }
+    }
+}
+
+impl Agent for HeuristicAgent {
+    fn choose_placement(
+        &mut self,
+        left: &Grid,
+        right: &Grid,
+        piece: &FallingPiece,
+    ) -> Option<Placement> {
+        let placements = ReachablePlacements::search(piece, left, right);
+
+        // Many placements only differ in one grid, so each grid's outcomes are scored once and then paired up.
+        let grid_outcomes = |side, grid| {
+            let shadow = Shadow::from_grid(grid);
+            let outcomes: Vec<(f32, ShadowOutcome)> = placements
+                .outcomes(side)
+                .iter()
+                .map(|cells| {
+                    let outcome = ShadowOutcome::lock_piece(&shadow, cells);
+                    (self.evaluator.evaluate_grid(&outcome), outcome)
+                })
+                .collect();
+            outcomes
+        };
+        let left_outcomes = grid_outcomes(GridSide::Left, left);
+        let right_outcomes = grid_outcomes(GridSide::Right, right);
+
+        placements
+            .placements()
+            .iter()
+            .map(|placement| {
+                let (left_score, left) = &left_outcomes[placement.left];
+                let (right_score, right) = &right_outcomes[placement.right];
+                let outcome = PlacementOutcome { left, right };
+                let score = self
+                    .evaluator
+                    .evaluate([*left_score, *right_score], outcome);
+                (score, placement)
+            })
+            // The first of equally good placements has the shortest path.
+            .reduce(|best, next| if next.0 > best.0 { next } else { best })
+            .map(|(_, placement)| Placement {
+                path: placements.path(placement),
+            })
+    }
+}
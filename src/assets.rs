@@ -1,5 +1,15 @@
-use crate::{CellValue, PieceType};
-use bevy::{color::palettes::css, prelude::*};
+use crate::{
+    pattern_texture, read_theme, CellValue, Config, PieceType, Theme, ThemeColors, ALL_PIECE_TYPES,
+};
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
+use std::path::PathBuf;
+
+/// Where the asset server finds `path`, for asset files that we read ourselves before there's any asset to load.
+pub fn asset_file_path(path: &str) -> PathBuf {
+    FileAssetReader::get_base_path()
+        .join(AssetPlugin::default().file_path)
+        .join(path)
+}
 
 #[derive(Resource)]
 pub struct SceneAssets {
@@ -9,11 +19,14 @@ pub struct SceneAssets {
     pub piece_materials: PieceMaterials,
 }
 
+/// Also sets the background color from the theme.
 pub fn create_scene_assets(
+    config: Res<Config>,
     mut commands: Commands,
     server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
 ) {
     let left_cell_mesh = meshes.add(
         Mesh::from(Rectangle {
@@ -29,7 +42,17 @@ pub fn create_scene_assets(
         half_size: 0.5 * Vec3::ONE,
     }));
 
-    let piece_materials = PieceMaterials::new(&server, &mut materials);
+    let (theme, colors) = read_theme(&config.theme);
+    let piece_materials = PieceMaterials::new(
+        &theme,
+        &colors,
+        config.piece_patterns,
+        &server,
+        &mut materials,
+        &mut images,
+    );
+
+    commands.insert_resource(ClearColor(colors.background));
 
     commands.insert_resource(SceneAssets {
         left_cell_mesh,
@@ -42,45 +65,64 @@ pub fn create_scene_assets(
 pub struct PieceMaterials {
     cell_materials: Vec<Handle<StandardMaterial>>,
     piece_materials: Vec<Handle<StandardMaterial>>,
+    ghost_materials: Vec<Handle<StandardMaterial>>,
     empty_cell_material: Handle<StandardMaterial>,
     drop_hint_material: Handle<StandardMaterial>,
+    suggested_hint_material: Handle<StandardMaterial>,
+    line_clear_material: Handle<StandardMaterial>,
+    garbage_material: Handle<StandardMaterial>,
 }
 
 impl PieceMaterials {
-    pub fn new(server: &AssetServer, materials: &mut Assets<StandardMaterial>) -> Self {
-        let empty_cell_material = materials.add(cell_material(css::GRAY.into()));
-        let drop_hint_material = materials.add(cell_material(css::DARK_GRAY.into()));
-
-        let piece_colors = [
-            css::RED,
-            css::GREEN,
-            css::BLUE,
-            css::YELLOW,
-            css::LIGHT_CYAN,
-            css::PINK,
-            css::ORANGE,
-            css::PURPLE,
-        ]
-        .map(Color::from);
-
-        let cell_materials = piece_colors
-            .iter()
-            .cloned()
-            .map(|c| materials.add(cell_material(c)))
-            .collect();
-
-        let bordered_texture: Handle<Image> = server.load("BorderedTile.png");
-        let piece_materials = piece_colors
-            .iter()
-            .cloned()
-            .map(|c| materials.add(piece_material(c, bordered_texture.clone())))
-            .collect();
+    /// With `piece_patterns`, each piece type's pieces and cells get their own pattern in place of the theme's
+    /// textures.
+    pub fn new(
+        theme: &Theme,
+        colors: &ThemeColors,
+        piece_patterns: bool,
+        server: &AssetServer,
+        materials: &mut Assets<StandardMaterial>,
+        images: &mut Assets<Image>,
+    ) -> Self {
+        let cell_texture: Option<Handle<Image>> =
+            theme.cell_texture.as_ref().map(|p| server.load(p.clone()));
+        let piece_texture: Option<Handle<Image>> =
+            theme.piece_texture.as_ref().map(|p| server.load(p.clone()));
+        let mut plain_cell_material =
+            |color| materials.add(cell_material(color, cell_texture.clone()));
+        let empty_cell_material = plain_cell_material(colors.empty_cell);
+        let drop_hint_material = plain_cell_material(colors.drop_hint);
+        let suggested_hint_material = plain_cell_material(colors.suggested_hint);
+        let line_clear_material = plain_cell_material(colors.line_clear);
+        let garbage_material = plain_cell_material(colors.garbage);
+
+        let mut cell_materials = Vec::new();
+        let mut piece_materials = Vec::new();
+        let mut ghost_materials = Vec::new();
+        for piece_type in ALL_PIECE_TYPES {
+            let color = colors.piece(piece_type);
+            let (cell_texture, piece_texture) = if piece_patterns {
+                (
+                    Some(images.add(pattern_texture(piece_type, false))),
+                    Some(images.add(pattern_texture(piece_type, true))),
+                )
+            } else {
+                (cell_texture.clone(), piece_texture.clone())
+            };
+            cell_materials.push(materials.add(cell_material(color, cell_texture)));
+            piece_materials.push(materials.add(cell_material(color, piece_texture)));
+            ghost_materials.push(materials.add(ghost_material(color)));
+        }
 
         Self {
             cell_materials,
             piece_materials,
+            ghost_materials,
             empty_cell_material,
             drop_hint_material,
+            suggested_hint_material,
+            line_clear_material,
+            garbage_material,
         }
     }
 
@@ -88,10 +130,16 @@ impl PieceMaterials {
         self.piece_materials[piece_type as usize].clone()
     }
 
+    pub fn get_ghost_material(&self, piece_type: PieceType) -> Handle<StandardMaterial> {
+        self.ghost_materials[piece_type as usize].clone()
+    }
+
     pub fn get_cell_material(&self, cell_value: CellValue) -> Handle<StandardMaterial> {
         match cell_value {
             CellValue::Piece(piece_type) => self.cell_materials[piece_type as usize].clone(),
+            CellValue::Garbage => self.garbage_material(),
             CellValue::DropHint => self.drop_hint_material(),
+            CellValue::SuggestedHint => self.suggested_hint_material(),
             CellValue::Empty => self.empty_cell_material(),
         }
     }
@@ -103,18 +151,32 @@ impl PieceMaterials {
     pub fn drop_hint_material(&self) -> Handle<StandardMaterial> {
         self.drop_hint_material.clone()
     }
+
+    pub fn suggested_hint_material(&self) -> Handle<StandardMaterial> {
+        self.suggested_hint_material.clone()
+    }
+
+    pub fn line_clear_material(&self) -> Handle<StandardMaterial> {
+        self.line_clear_material.clone()
+    }
+
+    pub fn garbage_material(&self) -> Handle<StandardMaterial> {
+        self.garbage_material.clone()
+    }
 }
 
-fn cell_material(color: Color) -> StandardMaterial {
+fn cell_material(color: Color, texture: Option<Handle<Image>>) -> StandardMaterial {
     let mut m = StandardMaterial::from(color);
+    m.base_color_texture = texture;
     m.unlit = true;
 
     m
 }
 
-fn piece_material(color: Color, texture: Handle<Image>) -> StandardMaterial {
-    let mut m = StandardMaterial::from(texture);
+fn ghost_material(color: Color) -> StandardMaterial {
+    let mut m = StandardMaterial::from(color.with_alpha(0.3));
     m.unlit = true;
-    m.base_color = color;
+    m.alpha_mode = AlphaMode::Blend;
+
     m
 }
@@ -0,0 +1,328 @@
+use crate::{
+    asset_file_path, is_dual_clear, Config, FallingPieceFeedback, FallingPieceOutcome, GameOver,
+    GameOverReason, GridSide, LevelUp, PieceCleared,
+};
+use bevy::{
+    audio::{PlaybackMode, Volume},
+    prelude::*,
+};
+use std::path::Path;
+
+/// The file format of a sound bank. Like the bank itself, all paths are relative to the `assets` directory, and any
+/// sound can be left out to silence it.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+pub struct SoundBankFile {
+    pub music: Option<String>,
+    pub moved: Option<String>,
+    pub rotated: Option<String>,
+    pub rotation_rejected: Option<String>,
+    pub locked_left: Option<String>,
+    pub locked_right: Option<String>,
+    /// Indexed by the number of rows cleared minus one. Bigger clears reuse the last sound.
+    pub line_clear: Vec<String>,
+    pub dual_clear: Option<String>,
+    pub level_up: Option<String>,
+    /// Played when a game is won by reaching the mode's goal.
+    pub victory: Option<String>,
+    pub game_over: Option<String>,
+}
+
+impl SoundBankFile {
+    pub fn read_file(path: &Path) -> Result<Self, ron::Error> {
+        let reader = std::fs::File::open(path)?;
+
+        ron::de::from_reader(reader)
+    }
+}
+
+#[derive(Resource)]
+pub struct SoundBank {
+    music: Option<Handle<AudioSource>>,
+    moved: Option<Handle<AudioSource>>,
+    rotated: Option<Handle<AudioSource>>,
+    rotation_rejected: Option<Handle<AudioSource>>,
+    locked_left: Option<Handle<AudioSource>>,
+    locked_right: Option<Handle<AudioSource>>,
+    line_clear: Vec<Handle<AudioSource>>,
+    dual_clear: Option<Handle<AudioSource>>,
+    level_up: Option<Handle<AudioSource>>,
+    victory: Option<Handle<AudioSource>>,
+    game_over: Option<Handle<AudioSource>>,
+}
+
+impl SoundBank {
+    pub fn load(file: &SoundBankFile, server: &AssetServer) -> Self {
+        let load = |path: &Option<String>| path.as_ref().map(|p| server.load(p.clone()));
+
+        Self {
+            music: load(&file.music),
+            moved: load(&file.moved),
+            rotated: load(&file.rotated),
+            rotation_rejected: load(&file.rotation_rejected),
+            locked_left: load(&file.locked_left),
+            locked_right: load(&file.locked_right),
+            line_clear: file
+                .line_clear
+                .iter()
+                .map(|p| server.load(p.clone()))
+                .collect(),
+            dual_clear: load(&file.dual_clear),
+            level_up: load(&file.level_up),
+            victory: load(&file.victory),
+            game_over: load(&file.game_over),
+        }
+    }
+
+    fn get(&self, cue: SoundCue) -> Option<Handle<AudioSource>> {
+        match cue {
+            SoundCue::Move => self.moved.clone(),
+            SoundCue::Rotate => self.rotated.clone(),
+            SoundCue::RotationRejected => self.rotation_rejected.clone(),
+            SoundCue::Lock(GridSide::Left) => self.locked_left.clone(),
+            SoundCue::Lock(GridSide::Right) => self.locked_right.clone(),
+            SoundCue::LineClear(count) => {
+                let i = count.clamp(1, self.line_clear.len().max(1)) - 1;
+                self.line_clear.get(i).cloned()
+            }
+            SoundCue::DualClear => self.dual_clear.clone(),
+            SoundCue::LevelUp => self.level_up.clone(),
+            SoundCue::Victory => self.victory.clone(),
+            SoundCue::GameOver => self.game_over.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SoundCue {
+    Move,
+    Rotate,
+    RotationRejected,
+    Lock(GridSide),
+    LineClear(usize),
+    DualClear,
+    LevelUp,
+    Victory,
+    GameOver,
+}
+
+/// Any system can send this to play a sound effect.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct PlaySound(pub SoundCue);
+
+#[derive(Component)]
+pub struct Music;
+
+/// A missing or broken sound bank is not fatal; the game just stays silent.
+pub fn load_sound_bank(config: Res<Config>, server: Res<AssetServer>, mut commands: Commands) {
+    let path = asset_file_path(&config.sound_bank);
+    let file = SoundBankFile::read_file(&path).unwrap_or_else(|e| {
+        warn!("Failed to read sound bank {}: {}", path.display(), e);
+        SoundBankFile::default()
+    });
+    commands.insert_resource(SoundBank::load(&file, &server));
+}
+
+pub fn start_music(config: Res<Config>, bank: Res<SoundBank>, mut commands: Commands) {
+    let volume = config.master_volume * config.music_volume;
+    if volume <= 0.0 {
+        return;
+    }
+    if let Some(music) = bank.music.clone() {
+        commands.spawn((
+            Music,
+            AudioPlayer(music),
+            PlaybackSettings {
+                mode: PlaybackMode::Loop,
+                volume: Volume::new(volume),
+                ..default()
+            },
+        ));
+    }
+}
+
+pub fn send_gameplay_sounds(
+    mut feedback: EventReader<FallingPieceFeedback>,
+    mut piece_cleared: EventReader<PieceCleared>,
+    mut level_ups: EventReader<LevelUp>,
+    mut game_over: EventReader<GameOver>,
+    mut sounds: EventWriter<PlaySound>,
+) {
+    for event in feedback.read() {
+        let cue = match event.outcome {
+            FallingPieceOutcome::Translated => SoundCue::Move,
+            FallingPieceOutcome::Rotated => SoundCue::Rotate,
+            FallingPieceOutcome::RotationRejected => SoundCue::RotationRejected,
+            FallingPieceOutcome::Locked(side) => SoundCue::Lock(side),
+            FallingPieceOutcome::Placed => continue,
+        };
+        sounds.send(PlaySound(cue));
+    }
+
+    // Clears in both grids from the same piece get a single, special sound.
+    for PieceCleared { clears, .. } in piece_cleared.read() {
+        if is_dual_clear(clears) {
+            sounds.send(PlaySound(SoundCue::DualClear));
+        } else {
+            for (_, count) in clears {
+                sounds.send(PlaySound(SoundCue::LineClear(*count)));
+            }
+        }
+    }
+
+    if level_ups.read().count() > 0 {
+        sounds.send(PlaySound(SoundCue::LevelUp));
+    }
+
+    for GameOver { reason, .. } in game_over.read() {
+        let cue = match reason {
+            GameOverReason::ToppedOut | GameOverReason::OutOfPieces => SoundCue::GameOver,
+            GameOverReason::Completed => SoundCue::Victory,
+            // Nobody won or lost, so there's nothing to celebrate or mourn.
+            GameOverReason::Disconnected => continue,
+        };
+        sounds.send(PlaySound(cue));
+    }
+}
+
+pub fn play_sounds(
+    config: Res<Config>,
+    bank: Option<Res<SoundBank>>,
+    mut events: EventReader<PlaySound>,
+    mut commands: Commands,
+) {
+    let volume = config.master_volume * config.sfx_volume;
+    let Some(bank) = bank.filter(|_| volume > 0.0) else {
+        // Muted, but we still need to drain the events.
+        events.clear();
+        return;
+    };
+
+    for PlaySound(cue) in events.read() {
+        if let Some(source) = bank.get(*cue) {
+            commands.spawn((
+                AudioPlayer(source),
+                PlaybackSettings::DESPAWN.with_volume(Volume::new(volume)),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{send_piece_cleared_events, Board, Grid, LinesCleared, PendingClears, Player};
+    use bevy::audio::AudioPlugin;
+
+    fn cue_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<LinesCleared>()
+            .add_event::<FallingPieceFeedback>()
+            .add_event::<PieceCleared>()
+            .add_event::<LevelUp>()
+            .add_event::<GameOver>()
+            .add_event::<PlaySound>()
+            .add_systems(
+                Update,
+                (send_piece_cleared_events, send_gameplay_sounds).chain(),
+            );
+        app.world_mut()
+            .spawn((Board, Player::One, PendingClears::default()));
+        app
+    }
+
+    fn sent_cues(app: &mut App) -> Vec<SoundCue> {
+        app.update();
+        let mut events = app.world_mut().resource_mut::<Events<PlaySound>>();
+        events.drain().map(|PlaySound(cue)| cue).collect()
+    }
+
+    fn clear(app: &mut App, side: GridSide, count: usize) {
+        let grid = app
+            .world_mut()
+            .spawn(Grid::headless(side, Player::One, [4, 8]))
+            .id();
+        app.world_mut().send_event(LinesCleared {
+            grid,
+            player: Player::One,
+            side,
+            rows: (0..count as i32).collect(),
+            count,
+        });
+    }
+
+    fn place(app: &mut App) {
+        app.world_mut().send_event(FallingPieceFeedback {
+            player: Player::One,
+            outcome: FallingPieceOutcome::Placed,
+        });
+    }
+
+    fn game_over(app: &mut App, reason: GameOverReason) {
+        app.world_mut().send_event(GameOver {
+            player: Player::One,
+            reason,
+        });
+    }
+
+    #[test]
+    fn clears_are_cued_once_the_piece_is_placed() {
+        let mut app = cue_app();
+        clear(&mut app, GridSide::Left, 2);
+        assert_eq!(sent_cues(&mut app), []);
+        place(&mut app);
+        assert_eq!(sent_cues(&mut app), [SoundCue::LineClear(2)]);
+    }
+
+    #[test]
+    fn a_split_lock_clearing_both_grids_is_a_dual_clear() {
+        let mut app = cue_app();
+        clear(&mut app, GridSide::Left, 1);
+        assert_eq!(sent_cues(&mut app), []);
+        clear(&mut app, GridSide::Right, 3);
+        place(&mut app);
+        assert_eq!(sent_cues(&mut app), [SoundCue::DualClear]);
+    }
+
+    #[test]
+    fn level_ups_and_game_overs_have_their_own_cues() {
+        let mut app = cue_app();
+        app.world_mut().send_event(LevelUp {
+            player: Player::One,
+            level: 2,
+        });
+        assert_eq!(sent_cues(&mut app), [SoundCue::LevelUp]);
+
+        game_over(&mut app, GameOverReason::Completed);
+        assert_eq!(sent_cues(&mut app), [SoundCue::Victory]);
+        game_over(&mut app, GameOverReason::ToppedOut);
+        assert_eq!(sent_cues(&mut app), [SoundCue::GameOver]);
+        game_over(&mut app, GameOverReason::OutOfPieces);
+        assert_eq!(sent_cues(&mut app), [SoundCue::GameOver]);
+        game_over(&mut app, GameOverReason::Disconnected);
+        assert_eq!(sent_cues(&mut app), []);
+    }
+
+    #[test]
+    fn sounds_play_without_an_audio_output() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            AudioPlugin::default(),
+        ))
+        .add_event::<PlaySound>()
+        .insert_resource(Config::default())
+        .add_systems(Startup, load_sound_bank)
+        .add_systems(Update, play_sounds);
+        app.update();
+        assert!(app.world().resource::<SoundBank>().moved.is_some());
+
+        for cue in [SoundCue::Move, SoundCue::LineClear(4), SoundCue::DualClear] {
+            app.world_mut().send_event(PlaySound(cue));
+        }
+        for _ in 0..10 {
+            app.update();
+        }
+    }
+}
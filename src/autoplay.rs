@@ -0,0 +1,101 @@
+use crate::{
+    Agent, Board, FallingPiece, FallingPieceAction, FallingPieceEvent, Grid, GridSide,
+    HeuristicAgent, Player, Remote,
+};
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+/// Seconds between the moves of an agent, so that people can follow along.
+const AUTOPLAY_MOVE_SECS: f32 = 0.05;
+
+/// The players whose boards are played by the built-in bot, from `--autoplay`.
+#[derive(Resource)]
+pub struct AutoplayPlayers(pub Vec<Player>);
+
+/// A board that an `Agent` plays instead of the controls.
+#[derive(Component)]
+pub struct Autoplay {
+    agent: Box<dyn Agent>,
+    /// The piece that `actions` were planned for.
+    piece: Option<Entity>,
+    actions: VecDeque<FallingPieceAction>,
+    timer: Timer,
+}
+
+impl Autoplay {
+    pub fn new(agent: Box<dyn Agent>) -> Self {
+        Self {
+            agent,
+            piece: None,
+            actions: VecDeque::new(),
+            timer: Timer::from_seconds(AUTOPLAY_MOVE_SECS, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Whether player one's board is played by the bot. Its games aren't the player's, so they aren't recorded.
+pub fn autoplaying(board_query: Query<&Player, (With<Board>, With<Autoplay>)>) -> bool {
+    board_query.iter().any(|player| *player == Player::One)
+}
+
+/// Should run after `create_game`.
+#[allow(clippy::type_complexity)]
+pub fn start_autoplay(
+    players: Res<AutoplayPlayers>,
+    board_query: Query<(Entity, &Player), (With<Board>, Without<Remote>)>,
+    mut commands: Commands,
+) {
+    for (board, player) in board_query.iter() {
+        if players.0.contains(player) {
+            commands
+                .entity(board)
+                .insert(Autoplay::new(Box::new(HeuristicAgent::default())));
+        }
+    }
+}
+
+/// Plans a placement whenever a new piece spawns, then plays it one move at a time through `FallingPieceEvent`s, just
+/// like the controls would.
+pub fn play_autoplay(
+    time: Res<Time>,
+    mut board_query: Query<(&Player, &mut Autoplay), With<Board>>,
+    falling_piece_query: Query<(Entity, &FallingPiece)>,
+    grid_query: Query<&Grid>,
+    mut events: EventWriter<FallingPieceEvent>,
+) {
+    for (player, mut autoplay) in board_query.iter_mut() {
+        let Some((piece_entity, piece)) = falling_piece_query
+            .iter()
+            .find(|(_, piece)| piece.player() == *player)
+        else {
+            autoplay.piece = None;
+            autoplay.actions.clear();
+            continue;
+        };
+
+        if autoplay.piece != Some(piece_entity) {
+            let grid = |side| {
+                grid_query
+                    .iter()
+                    .find(|g| g.player() == *player && g.side() == side)
+            };
+            let (Some(left), Some(right)) = (grid(GridSide::Left), grid(GridSide::Right)) else {
+                continue;
+            };
+            let actions = match autoplay.agent.choose_placement(left, right, piece) {
+                Some(placement) => placement.path,
+                None => vec![FallingPieceAction::FastDrop],
+            };
+            autoplay.piece = Some(piece_entity);
+            autoplay.actions = actions.into();
+            autoplay.timer.reset();
+        }
+
+        if !autoplay.timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        if let Some(action) = autoplay.actions.pop_front() {
+            events.send(FallingPieceEvent::new(*player, action));
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use projectris::{
+    Action, Agent, Config, ConfigSource, Environment, HeuristicAgent, ALL_ACTIONS, CONFIG_PATH,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str = "usage: projectris-batch [--episodes N] [--seed N] [--policy random|heuristic] [--max-steps N] \
+[--steps-per-drop N] [--config FILE] [--output FILE]";
+
+/// Plays episodes in the headless `Environment` and writes one CSV row of stats per episode, to stdout unless given
+/// `--output`. Episode `i` uses seed `seed + i`, so runs can be repeated. The grid size and bag come from `--config`,
+/// or `config.ron` in the current directory if there is one, or else the default config.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut episodes = 10;
+    let mut seed = 0;
+    let mut policy = Policy::Random;
+    let mut max_steps = 100_000;
+    let mut steps_per_drop = 5;
+    let mut config_path: Option<PathBuf> = None;
+    let mut output: Box<dyn Write> = Box::new(std::io::stdout());
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        match flag.as_str() {
+            "--episodes" => episodes = value.parse()?,
+            "--seed" => seed = value.parse()?,
+            "--policy" => {
+                policy = match value.as_str() {
+                    "random" => Policy::Random,
+                    "heuristic" => Policy::Heuristic,
+                    _ => return Err(USAGE.into()),
+                }
+            }
+            "--max-steps" => max_steps = value.parse()?,
+            "--steps-per-drop" => steps_per_drop = value.parse()?,
+            "--config" => config_path = Some(value.into()),
+            "--output" => output = Box::new(std::fs::File::create(value)?),
+            _ => return Err(USAGE.into()),
+        }
+    }
+
+    let config = match config_path {
+        Some(path) => ConfigSource {
+            path,
+            overrides: Vec::new(),
+        }
+        .read()?,
+        None if Path::new(CONFIG_PATH).exists() => ConfigSource::default().read()?,
+        None => Config::default(),
+    };
+    let mut env = Environment::new(config.grid_size, config.repeats_per_bag, steps_per_drop);
+    writeln!(
+        output,
+        "episode,seed,steps,pieces,left_lines,right_lines,score,reward,topped_out,seconds"
+    )?;
+    for episode in 0..episodes {
+        let episode_seed = seed + episode;
+        let start = std::time::Instant::now();
+        let reward = play_episode(&mut env, policy, episode_seed, max_steps);
+        let stats = env.stats();
+        writeln!(
+            output,
+            "{},{},{},{},{},{},{},{},{},{:.3}",
+            episode,
+            episode_seed,
+            env.steps(),
+            stats.pieces_placed(),
+            stats.lines(projectris::GridSide::Left),
+            stats.lines(projectris::GridSide::Right),
+            stats.score(),
+            reward,
+            env.is_done(),
+            start.elapsed().as_secs_f64(),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Policy {
+    /// Picks any action, which is mostly useful for measuring how fast the environment is.
+    Random,
+    /// Plays the same bot as `--autoplay`.
+    Heuristic,
+}
+
+/// Returns the total reward.
+fn play_episode(env: &mut Environment, policy: Policy, seed: u64, max_steps: u32) -> f32 {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut agent = HeuristicAgent::default();
+    let mut planned: VecDeque<Action> = VecDeque::new();
+    let mut total_reward = 0.0;
+
+    env.reset(seed);
+    while !env.is_done() && env.steps() < max_steps {
+        let action = match policy {
+            Policy::Random => *ALL_ACTIONS.choose(&mut rng).unwrap(),
+            Policy::Heuristic => {
+                if planned.is_empty() {
+                    let [left, right] = env.grids();
+                    planned = agent
+                        .choose_placement(left, right, env.piece())
+                        .map_or_else(Vec::new, |placement| placement.path)
+                        .iter()
+                        .filter_map(Action::from_falling_piece_action)
+                        .collect();
+                }
+                planned.pop_front().unwrap_or(Action::FastDrop)
+            }
+        };
+
+        let (_, reward, _, info) = env.step(action);
+        total_reward += reward;
+        if info.placed {
+            planned.clear();
+        }
+    }
+
+    total_reward
+}
@@ -0,0 +1,14 @@
+use projectris::{Server, DEFAULT_SERVER_ADDRESS};
+use std::net::TcpListener;
+
+/// A headless server for online versus games. Pass an address to listen on something other than the default.
+fn main() -> std::io::Result<()> {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_SERVER_ADDRESS.to_string());
+
+    let mut server = Server::new(TcpListener::bind(&address)?)?;
+    println!("Listening on {}", server.local_addr()?);
+
+    server.run()
+}
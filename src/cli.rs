@@ -0,0 +1,196 @@
+use crate::{GameMode, Player, ALL_GAME_MODES, CONFIG_PATH};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: projectris [options] [field=value ...]
+
+Options:
+  --config <path>          Read the config from <path> instead of config.ron
+  --check-config           Check the config and exit
+  --mode <name>            Skip the main menu and start a game in this mode
+  --seed <number>          Play every game with this seed, for the same pieces and garbage
+  --replay <path>          Play back a replay, in its mode and with its seed
+  --record <path>          Save a replay of each game to <path> when it ends
+  --autoplay [1|2]         Let the bot play every board, or only player one's or two's
+  --window-size <w>x<h>    Open the window at this size, e.g. 1280x720
+  --fullscreen             Open the window in fullscreen
+  --headless               Run without a window, and exit with the results when the game ends
+  --connect <address>      Go straight to the online lobby of a server
+  --spectate <address>     Watch games on a server
+  --broadcast <address>    Let local games be watched through a server
+  --help                   Show this message
+
+Any config field can be overridden with field=value, with the value written as it would be in the config, e.g.
+grid_size=(8,16) or player_one_keys.fast_drop=KeyX.";
+
+/// What the game was asked to do on the command line.
+#[derive(Debug)]
+pub struct Cli {
+    pub config_path: PathBuf,
+    pub check_config: bool,
+    pub mode: Option<GameMode>,
+    pub seed: Option<u64>,
+    pub replay: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub autoplay: Option<Vec<Player>>,
+    pub window_size: Option<[u32; 2]>,
+    pub fullscreen: bool,
+    pub headless: bool,
+    pub connect: Option<String>,
+    pub spectate: Option<String>,
+    pub broadcast: Option<String>,
+    pub help: bool,
+    /// Config field paths and their values, in the order they were given.
+    pub overrides: Vec<(String, String)>,
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        Self {
+            config_path: CONFIG_PATH.into(),
+            check_config: false,
+            mode: None,
+            seed: None,
+            replay: None,
+            record: None,
+            autoplay: None,
+            window_size: None,
+            fullscreen: false,
+            headless: false,
+            connect: None,
+            spectate: None,
+            broadcast: None,
+            help: false,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl Cli {
+    /// `args` leaves out the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--config" => cli.config_path = value()?.into(),
+                "--check-config" => cli.check_config = true,
+                "--mode" => cli.mode = Some(parse_mode(&value()?)?),
+                "--seed" => {
+                    let seed = value()?;
+                    cli.seed = Some(
+                        seed.parse()
+                            .map_err(|_| format!("{} isn't a valid seed", seed))?,
+                    );
+                }
+                "--replay" => cli.replay = Some(value()?.into()),
+                "--record" => cli.record = Some(value()?.into()),
+                "--autoplay" => {
+                    let players = match args.peek().map(String::as_str) {
+                        Some("1") => vec![Player::One],
+                        Some("2") => vec![Player::Two],
+                        _ => vec![Player::One, Player::Two],
+                    };
+                    if players.len() == 1 {
+                        args.next();
+                    }
+                    cli.autoplay = Some(players);
+                }
+                "--window-size" => cli.window_size = Some(parse_window_size(&value()?)?),
+                "--fullscreen" => cli.fullscreen = true,
+                "--headless" => cli.headless = true,
+                "--connect" => cli.connect = Some(value()?),
+                "--spectate" => cli.spectate = Some(value()?),
+                "--broadcast" => cli.broadcast = Some(value()?),
+                "--help" | "-h" => cli.help = true,
+                _ => match arg.split_once('=') {
+                    Some((path, value)) if !arg.starts_with('-') => {
+                        cli.overrides.push((path.to_string(), value.to_string()))
+                    }
+                    _ => return Err(format!("Unknown argument {}", arg)),
+                },
+            }
+        }
+
+        if cli.replay.is_some() && (cli.mode.is_some() || cli.seed.is_some()) {
+            return Err("--replay already sets the mode and the seed".into());
+        }
+        if cli.replay.is_some() && cli.autoplay.is_some() {
+            return Err("--replay and --autoplay can't both play the game".into());
+        }
+        if cli.replay.is_some() && cli.record.is_some() {
+            return Err("--replay is already recorded".into());
+        }
+        if cli.headless && (cli.connect.is_some() || cli.spectate.is_some()) {
+            return Err("--headless can't play online or spectate".into());
+        }
+
+        Ok(cli)
+    }
+}
+
+fn parse_mode(name: &str) -> Result<GameMode, String> {
+    GameMode::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = ALL_GAME_MODES.iter().map(|m| m.name()).collect();
+        format!(
+            "Unknown mode {}, expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn parse_window_size(size: &str) -> Result<[u32; 2], String> {
+    let error = || format!("{} isn't a window size like 1280x720", size);
+    let (width, height) = size.split_once('x').ok_or_else(error)?;
+
+    Ok([
+        width.parse().map_err(|_| error())?,
+        height.parse().map_err(|_| error())?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Cli, String> {
+        Cli::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn flags_and_overrides_are_parsed() {
+        let cli = parse("--mode sprint --seed 7 --autoplay 2 grid_size=(8,16) --headless").unwrap();
+        assert_eq!(cli.mode, Some(GameMode::Sprint));
+        assert_eq!(cli.seed, Some(7));
+        assert_eq!(cli.autoplay, Some(vec![Player::Two]));
+        assert!(cli.headless);
+        assert_eq!(
+            cli.overrides,
+            [("grid_size".to_string(), "(8,16)".to_string())]
+        );
+    }
+
+    #[test]
+    fn replays_conflict_with_other_ways_of_playing() {
+        assert!(parse("--replay game.ron").is_ok());
+        assert!(parse("--replay game.ron --record copy.ron").is_err());
+        assert!(parse("--replay game.ron --autoplay").is_err());
+        assert!(parse("--autoplay --replay game.ron").is_err());
+        assert!(parse("--replay game.ron --seed 3").is_err());
+    }
+
+    #[test]
+    fn a_flag_without_its_value_is_an_error() {
+        assert_eq!(parse("--record").unwrap_err(), "--record needs a value");
+        assert!(parse("--seed").is_err());
+        assert!(parse("--seed seven").is_err());
+    }
+
+    #[test]
+    fn unknown_arguments_are_errors() {
+        assert_eq!(parse("--sped 3").unwrap_err(), "Unknown argument --sped");
+        assert!(parse("sprint").is_err());
+        assert!(parse("--grid_size=(8,16)").is_err());
+    }
+}
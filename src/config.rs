@@ -1,20 +1,223 @@
+use crate::{FallingPiece, Player, ALL_PIECE_TYPES};
 use bevy::{
     math::Vec3,
-    prelude::{KeyCode, Resource},
+    prelude::{GamepadButton, KeyCode, Resource},
+    reflect::{serde::TypedReflectDeserializer, GetPath, Reflect, TypeRegistry},
 };
+use serde::de::DeserializeSeed;
+use std::any::TypeId;
 
-#[derive(Resource, serde::Deserialize, serde::Serialize)]
+/// Any field can be left out of `config.ron`, and then it takes its value from `Config::default`.
+#[derive(Clone, Reflect, Resource, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct Config {
     pub grid_size: [usize; 2],
     pub grid_offset: f32,
     pub grid_tilt_angle: f32,
+    /// How long the falling piece takes to slide into place when it moves or drops. 0 snaps it there.
+    pub piece_move_secs: f32,
+    /// How long the falling piece takes to turn when it rotates. 0 snaps it there.
+    pub piece_rotate_secs: f32,
+    /// Draws rays from the falling piece's cubes to their cells in each grid.
+    pub projection_guides: bool,
+    /// From 0, invisible, to 1, solid.
+    pub projection_guide_opacity: f32,
+    /// The colors and textures of the board. Like the textures in it, the theme is relative to the `assets` directory,
+    /// where `themes` has the default theme and colorblind-safe ones.
+    pub theme: String,
+    /// Gives each piece type its own pattern, so pieces can be told apart without their colors.
+    pub piece_patterns: bool,
     pub camera_position: Vec3,
     pub camera_target: Vec3,
+    /// The mouse and gamepad sticks move the camera too. The last camera is remembered between games.
+    pub camera_keys: CameraKeys,
     pub repeats_per_bag: usize,
+    pub player_one_keys: KeyBindings,
+    pub player_two_keys: KeyBindings,
+    /// Shared by every player. Each player uses the gamepad with the same index, in the order they were connected.
+    pub gamepad_buttons: GamepadBindings,
+    /// Shows or hides the suggested placement, in modes that allow it.
+    pub toggle_hints_key: KeyCode,
+    pub line_clear_flash_time: f32,
+    pub line_clear_collapse_time: f32,
+    /// Relative to the `assets` directory, like the sounds in it.
+    pub sound_bank: String,
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub sprint_target_lines: u32,
+    pub ultra_time_limit_secs: u32,
+    pub marathon_levels: u32,
+    pub dig_target_lines: u32,
+    pub dig_initial_garbage_rows: u32,
+    pub dig_garbage_rise_secs: f32,
+    /// The modifiers used to be here, before each player had their own keys. They're still read so that `validate` can
+    /// point old configs at `player_one_keys`, instead of them being silently ignored.
+    #[reflect(ignore)]
+    #[serde(skip_serializing, deserialize_with = "deserialize_legacy_key")]
+    pub left_rotate_modifier: Option<KeyCode>,
+    #[reflect(ignore)]
+    #[serde(skip_serializing, deserialize_with = "deserialize_legacy_key")]
+    pub left_translate_modifier: Option<KeyCode>,
+    #[reflect(ignore)]
+    #[serde(skip_serializing, deserialize_with = "deserialize_legacy_key")]
+    pub right_translate_modifier: Option<KeyCode>,
+    #[reflect(ignore)]
+    #[serde(skip_serializing, deserialize_with = "deserialize_legacy_key")]
+    pub right_rotate_modifier: Option<KeyCode>,
+}
+
+/// The legacy modifiers were written without `Some`.
+fn deserialize_legacy_key<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<KeyCode>, D::Error> {
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+/// Holding one of the modifiers picks what `move_left` and `move_right` do.
+#[derive(Clone, Reflect, serde::Deserialize, serde::Serialize)]
+pub struct KeyBindings {
     pub left_rotate_modifier: KeyCode,
     pub left_translate_modifier: KeyCode,
     pub right_translate_modifier: KeyCode,
     pub right_rotate_modifier: KeyCode,
+    pub move_left: KeyCode,
+    pub move_right: KeyCode,
+    pub fast_drop: KeyCode,
+}
+
+/// The same layout as `KeyBindings`, for gamepads.
+#[derive(Clone, Reflect, serde::Deserialize, serde::Serialize)]
+pub struct GamepadBindings {
+    pub left_rotate_modifier: GamepadButton,
+    pub left_translate_modifier: GamepadButton,
+    pub right_translate_modifier: GamepadButton,
+    pub right_rotate_modifier: GamepadButton,
+    pub move_left: GamepadButton,
+    pub move_right: GamepadButton,
+    pub fast_drop: GamepadButton,
+}
+
+/// Jumps to a preset camera during a game, or back to `camera_position` and `camera_target`.
+#[derive(Clone, Reflect, serde::Deserialize, serde::Serialize)]
+pub struct CameraKeys {
+    pub isometric: KeyCode,
+    pub left_grid: KeyCode,
+    pub right_grid: KeyCode,
+    pub top_down: KeyCode,
+    pub reset: KeyCode,
+}
+
+impl KeyBindings {
+    fn named_keys(&self) -> [(&'static str, KeyCode); 7] {
+        [
+            ("left_rotate_modifier", self.left_rotate_modifier),
+            ("left_translate_modifier", self.left_translate_modifier),
+            ("right_translate_modifier", self.right_translate_modifier),
+            ("right_rotate_modifier", self.right_rotate_modifier),
+            ("move_left", self.move_left),
+            ("move_right", self.move_right),
+            ("fast_drop", self.fast_drop),
+        ]
+    }
+}
+
+impl GamepadBindings {
+    fn named_buttons(&self) -> [(&'static str, GamepadButton); 7] {
+        [
+            ("left_rotate_modifier", self.left_rotate_modifier),
+            ("left_translate_modifier", self.left_translate_modifier),
+            ("right_translate_modifier", self.right_translate_modifier),
+            ("right_rotate_modifier", self.right_rotate_modifier),
+            ("move_left", self.move_left),
+            ("move_right", self.move_right),
+            ("fast_drop", self.fast_drop),
+        ]
+    }
+}
+
+impl CameraKeys {
+    fn named_keys(&self) -> [(&'static str, KeyCode); 5] {
+        [
+            ("isometric", self.isometric),
+            ("left_grid", self.left_grid),
+            ("right_grid", self.right_grid),
+            ("top_down", self.top_down),
+            ("reset", self.reset),
+        ]
+    }
+}
+
+/// The value of every field that `config.ron` leaves out. These are also the values in the `config.ron` that ships
+/// with the game.
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            grid_size: [10, 20],
+            grid_offset: 5.0,
+            grid_tilt_angle: 0.4,
+            piece_move_secs: 0.05,
+            piece_rotate_secs: 0.08,
+            projection_guides: false,
+            projection_guide_opacity: 0.15,
+            theme: "themes/default.ron".into(),
+            piece_patterns: false,
+            camera_position: Vec3::new(-21.0, 10.0, -21.0),
+            camera_target: Vec3::new(5.0, 10.0, 5.0),
+            camera_keys: CameraKeys {
+                isometric: KeyCode::Digit1,
+                left_grid: KeyCode::Digit2,
+                right_grid: KeyCode::Digit3,
+                top_down: KeyCode::Digit4,
+                reset: KeyCode::KeyR,
+            },
+            repeats_per_bag: 3,
+            player_one_keys: KeyBindings {
+                left_rotate_modifier: KeyCode::KeyA,
+                left_translate_modifier: KeyCode::KeyS,
+                right_translate_modifier: KeyCode::KeyD,
+                right_rotate_modifier: KeyCode::KeyF,
+                move_left: KeyCode::ArrowLeft,
+                move_right: KeyCode::ArrowRight,
+                fast_drop: KeyCode::Space,
+            },
+            player_two_keys: KeyBindings {
+                left_rotate_modifier: KeyCode::Numpad7,
+                left_translate_modifier: KeyCode::Numpad8,
+                right_translate_modifier: KeyCode::Numpad9,
+                right_rotate_modifier: KeyCode::NumpadSubtract,
+                move_left: KeyCode::Numpad4,
+                move_right: KeyCode::Numpad6,
+                fast_drop: KeyCode::Numpad0,
+            },
+            gamepad_buttons: GamepadBindings {
+                left_rotate_modifier: GamepadButton::LeftTrigger2,
+                left_translate_modifier: GamepadButton::LeftTrigger,
+                right_translate_modifier: GamepadButton::RightTrigger,
+                right_rotate_modifier: GamepadButton::RightTrigger2,
+                move_left: GamepadButton::DPadLeft,
+                move_right: GamepadButton::DPadRight,
+                fast_drop: GamepadButton::South,
+            },
+            toggle_hints_key: KeyCode::KeyH,
+            line_clear_flash_time: 0.3,
+            line_clear_collapse_time: 0.15,
+            sound_bank: "sounds/default_bank.ron".into(),
+            master_volume: 1.0,
+            music_volume: 0.5,
+            sfx_volume: 0.8,
+            sprint_target_lines: 40,
+            ultra_time_limit_secs: 180,
+            marathon_levels: 15,
+            dig_target_lines: 20,
+            dig_initial_garbage_rows: 8,
+            dig_garbage_rise_secs: 10.0,
+            left_rotate_modifier: None,
+            left_translate_modifier: None,
+            right_translate_modifier: None,
+            right_rotate_modifier: None,
+        }
+    }
 }
 
 impl Config {
@@ -23,4 +226,272 @@ impl Config {
 
         ron::de::from_reader(reader)
     }
+
+    /// Sets the field at `path`, like `grid_size` or `player_one_keys.fast_drop`, to `value` written as it would be in
+    /// `config.ron`. Strings can be written without their quotes.
+    pub fn set_field(&mut self, path: &str, value: &str) -> Result<(), String> {
+        let mut registry = TypeRegistry::default();
+        registry.register::<Config>();
+
+        let field = self
+            .reflect_path_mut(path)
+            .map_err(|_| format!("{} isn't a config field", path))?;
+        let registration = field
+            .get_represented_type_info()
+            .and_then(|info| registry.get(info.type_id()))
+            .ok_or_else(|| format!("{} can't be set on its own", path))?;
+        let value = if registration.type_id() == TypeId::of::<String>() && !value.starts_with('"') {
+            format!("{:?}", value)
+        } else {
+            value.to_string()
+        };
+        // Reflection deserializes with the same version of RON as Bevy.
+        let mut deserializer = bevy::asset::ron::de::Deserializer::from_str(&value)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let value = TypedReflectDeserializer::new(registration, &registry)
+            .deserialize(&mut deserializer)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        field
+            .try_apply(value.as_partial_reflect())
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Checks the values that would otherwise break the game or make it unplayable, and reports every problem found.
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut errors = ConfigErrors::default();
+
+        let [width, height] = self.grid_size;
+        for piece_type in ALL_PIECE_TYPES {
+            let piece = FallingPiece::at_spawn(piece_type, Player::One, self.grid_size);
+            let fits = piece.cell_positions().iter().all(|p| {
+                (0..width as i32).contains(&p.x)
+                    && (0..height as i32).contains(&p.y)
+                    && (0..width as i32).contains(&p.z)
+            });
+            if !fits {
+                errors.push(
+                    "grid_size",
+                    format!(
+                        "the {:?} piece doesn't fit at the top of a {}x{} grid",
+                        piece_type, width, height
+                    ),
+                );
+            }
+        }
+        if self.dig_initial_garbage_rows as usize >= height {
+            errors.push(
+                "dig_initial_garbage_rows",
+                format!("must be less than the grid height, {}", height),
+            );
+        }
+
+        if self.repeats_per_bag == 0 {
+            errors.push("repeats_per_bag", "must be at least 1");
+        }
+        for (path, value) in [
+            ("sprint_target_lines", self.sprint_target_lines),
+            ("ultra_time_limit_secs", self.ultra_time_limit_secs),
+            ("marathon_levels", self.marathon_levels),
+            ("dig_target_lines", self.dig_target_lines),
+        ] {
+            if value == 0 {
+                errors.push(path, "must be at least 1");
+            }
+        }
+        if self.dig_garbage_rise_secs.is_nan() || self.dig_garbage_rise_secs <= 0.0 {
+            errors.push("dig_garbage_rise_secs", "must be more than 0");
+        }
+        for (path, value) in [
+            ("piece_move_secs", self.piece_move_secs),
+            ("piece_rotate_secs", self.piece_rotate_secs),
+            ("line_clear_flash_time", self.line_clear_flash_time),
+            ("line_clear_collapse_time", self.line_clear_collapse_time),
+        ] {
+            if value.is_nan() || value < 0.0 {
+                errors.push(path, "can't be negative");
+            }
+        }
+        for (path, value) in [
+            ("projection_guide_opacity", self.projection_guide_opacity),
+            ("master_volume", self.master_volume),
+            ("music_volume", self.music_volume),
+            ("sfx_volume", self.sfx_volume),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                errors.push(path, "must be between 0 and 1");
+            }
+        }
+        if self.camera_position == self.camera_target {
+            errors.push("camera_target", "must be away from camera_position");
+        }
+
+        for (path, key) in [
+            ("left_rotate_modifier", self.left_rotate_modifier),
+            ("left_translate_modifier", self.left_translate_modifier),
+            ("right_translate_modifier", self.right_translate_modifier),
+            ("right_rotate_modifier", self.right_rotate_modifier),
+        ] {
+            if key.is_some() {
+                errors.push(path, format!("has moved to player_one_keys.{}", path));
+            }
+        }
+
+        // Every key does one thing, whichever player or screen it belongs to.
+        let mut keys = Vec::new();
+        for (parent, named_keys) in [
+            (
+                "player_one_keys",
+                self.player_one_keys.named_keys().to_vec(),
+            ),
+            (
+                "player_two_keys",
+                self.player_two_keys.named_keys().to_vec(),
+            ),
+            ("camera_keys", self.camera_keys.named_keys().to_vec()),
+        ]
+        .iter()
+        {
+            keys.extend(
+                named_keys
+                    .iter()
+                    .map(|(name, key)| (format!("{}.{}", parent, name), *key)),
+            );
+        }
+        keys.push(("toggle_hints_key".to_string(), self.toggle_hints_key));
+        check_unique(keys, &mut errors);
+        let buttons = self
+            .gamepad_buttons
+            .named_buttons()
+            .iter()
+            .map(|(name, button)| (format!("gamepad_buttons.{}", name), *button))
+            .collect();
+        check_unique(buttons, &mut errors);
+
+        if errors.0.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Reports every binding that reuses an earlier one.
+fn check_unique<T: PartialEq + std::fmt::Debug>(
+    bindings: Vec<(String, T)>,
+    errors: &mut ConfigErrors,
+) {
+    let mut seen: Vec<(String, T)> = Vec::new();
+    for (path, binding) in bindings {
+        if let Some((other, _)) = seen.iter().find(|(_, b)| *b == binding) {
+            let message = format!("{:?} is already bound to {}", binding, other);
+            errors.push(path, message);
+        } else {
+            seen.push((path, binding));
+        }
+    }
+}
+
+/// A problem with one field of a `Config`. The path is the field's name, and nested fields are separated by dots, like
+/// `player_one_keys.fast_drop`.
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Every problem that `Config::validate` found.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl ConfigErrors {
+    fn push(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ConfigError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+}
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let lines: Vec<String> = self
+            .0
+            .iter()
+            .map(|e| format!("{}: {}", e.path, e.message))
+            .collect();
+
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_paths(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.0.into_iter().map(|e| e.path).collect(),
+        }
+    }
+
+    #[test]
+    fn legacy_modifiers_are_reported_by_path() {
+        let config: Config = ron::de::from_str("(left_translate_modifier: KeyQ)").unwrap();
+        assert_eq!(config.left_translate_modifier, Some(KeyCode::KeyQ));
+        assert_eq!(error_paths(&config), ["left_translate_modifier"]);
+    }
+
+    #[test]
+    fn the_default_config_is_valid() {
+        assert_eq!(error_paths(&Config::default()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn a_grid_too_small_for_the_pieces_is_reported() {
+        let config = Config {
+            grid_size: [2, 10],
+            ..Config::default()
+        };
+        let paths = error_paths(&config);
+        assert!(!paths.is_empty());
+        assert!(paths.iter().all(|path| path == "grid_size"));
+    }
+
+    #[test]
+    fn negative_durations_are_reported() {
+        let config = Config {
+            piece_move_secs: -0.1,
+            ..Config::default()
+        };
+        assert_eq!(error_paths(&config), ["piece_move_secs"]);
+    }
+
+    #[test]
+    fn keys_bound_twice_are_reported_where_they_are_bound_again() {
+        let mut config = Config::default();
+        config.player_two_keys.move_left = config.player_one_keys.fast_drop;
+        let Err(errors) = config.validate() else {
+            panic!("the duplicate key wasn't reported");
+        };
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].path, "player_two_keys.move_left");
+        assert!(errors.0[0].message.contains("player_one_keys.fast_drop"));
+    }
+
+    #[test]
+    fn nested_fields_can_be_set_by_path() {
+        let mut config = Config::default();
+        config
+            .set_field("player_one_keys.move_left", "KeyQ")
+            .unwrap();
+        assert_eq!(config.player_one_keys.move_left, KeyCode::KeyQ);
+
+        assert!(config.set_field("player_one_keys.jump", "KeyQ").is_err());
+        assert!(config.set_field("player_one_keys.move_left", "Q").is_err());
+        assert_eq!(config.player_one_keys.move_left, KeyCode::KeyQ);
+    }
 }
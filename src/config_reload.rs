@@ -0,0 +1,287 @@
+use crate::{grid_transform, CameraPose, Config, Grid, OrbitCamera};
+use bevy::{
+    asset::{io::Reader, AssetLoadFailedEvent, AssetLoader, LoadContext},
+    prelude::*,
+};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+pub const CONFIG_PATH: &str = "config.ron";
+/// An asset source for the directory that the config is in, since it usually isn't in `assets`.
+pub const CONFIG_SOURCE: &str = "config";
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// `config.ron`, loaded through the asset server so that it can be reloaded while the game runs.
+#[derive(Asset, TypePath)]
+pub struct ConfigAsset(pub Config);
+
+#[derive(Default)]
+pub struct ConfigLoader;
+
+impl AssetLoader for ConfigLoader {
+    type Asset = ConfigAsset;
+    type Settings = ();
+    type Error = ron::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<ConfigAsset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(ConfigAsset(ron::de::from_bytes(&bytes)?))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// Where the config comes from: a file, plus overrides for some of its fields from the command line, which are applied
+/// again every time the file is reloaded.
+#[derive(Clone, Debug, Resource)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    /// Field paths and values, as taken by `Config::set_field`.
+    pub overrides: Vec<(String, String)>,
+}
+
+impl Default for ConfigSource {
+    fn default() -> Self {
+        Self {
+            path: CONFIG_PATH.into(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl ConfigSource {
+    pub fn read(&self) -> Result<Config, Box<dyn std::error::Error>> {
+        let path = self.path.to_string_lossy();
+        let mut config = Config::read_file(&path).map_err(|e| format!("{}: {}", path, e))?;
+        self.apply(&mut config)?;
+
+        Ok(config)
+    }
+
+    /// Applies the overrides, then checks the result.
+    pub fn apply(&self, config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+        for (path, value) in self.overrides.iter() {
+            config.set_field(path, value)?;
+        }
+        config.validate()?;
+
+        Ok(())
+    }
+
+    /// The directory for the `CONFIG_SOURCE` asset source.
+    pub fn dir(&self) -> &Path {
+        match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        }
+    }
+
+    fn asset_path(&self) -> String {
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+
+        format!("{}://{}", CONFIG_SOURCE, file_name)
+    }
+
+    fn modified_time(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+}
+
+/// The asset server only notices changes to files with the `file_watcher` feature, so `config.ron` is watched by
+/// polling its modification time instead.
+#[derive(Resource)]
+pub struct ConfigFile {
+    handle: Handle<ConfigAsset>,
+    modified: Option<SystemTime>,
+    poll_timer: Timer,
+}
+
+/// Problems with the last reload of `config.ron`, shown on screen until they're fixed.
+#[derive(Default, Resource)]
+pub struct ConfigStatus {
+    /// The game keeps running with the last config that could be read.
+    pub error: Option<String>,
+    /// Settings that were changed, but are only read when the game starts.
+    pub restart_needed: Vec<&'static str>,
+}
+
+#[derive(Component)]
+pub struct ConfigStatusText;
+
+pub fn load_config_asset(
+    source: Res<ConfigSource>,
+    server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    commands.insert_resource(ConfigFile {
+        handle: server.load(source.asset_path()),
+        modified: source.modified_time(),
+        poll_timer: Timer::new(CONFIG_POLL_INTERVAL, TimerMode::Repeating),
+    });
+}
+
+/// Uses real time, so that the config can be edited while the game is paused.
+pub fn poll_config_file(
+    time: Res<Time<Real>>,
+    source: Res<ConfigSource>,
+    server: Res<AssetServer>,
+    mut file: ResMut<ConfigFile>,
+) {
+    if !file.poll_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = source.modified_time();
+    if modified != file.modified {
+        file.modified = modified;
+        server.reload(source.asset_path());
+    }
+}
+
+/// Applies a reloaded config. Most settings are read every frame, so replacing the `Config` is enough, but the camera
+/// and the grids are moved to match. Settings that are only read at startup keep their old values until a restart.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_config_changes(
+    source: Res<ConfigSource>,
+    file: Res<ConfigFile>,
+    assets: Res<Assets<ConfigAsset>>,
+    mut asset_events: EventReader<AssetEvent<ConfigAsset>>,
+    mut failed_events: EventReader<AssetLoadFailedEvent<ConfigAsset>>,
+    mut config: ResMut<Config>,
+    mut status: ResMut<ConfigStatus>,
+    orbit_camera: Option<ResMut<OrbitCamera>>,
+    mut grid_query: Query<(&Grid, &mut Transform)>,
+) {
+    for event in failed_events.read() {
+        if event.id == file.handle.id() {
+            warn!(
+                "Failed to reload {}: {}",
+                source.path.display(),
+                event.error
+            );
+            status.error = Some(event.error.to_string());
+        }
+    }
+
+    let reloaded = asset_events.read().any(|event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+            *id == file.handle.id()
+        }
+        _ => false,
+    });
+    let Some(ConfigAsset(new_config)) = assets.get(&file.handle).filter(|_| reloaded) else {
+        return;
+    };
+    let mut new_config = new_config.clone();
+    if let Err(e) = source.apply(&mut new_config) {
+        warn!("Not applying {}:\n{}", source.path.display(), e);
+        status.error = Some(e.to_string());
+        return;
+    }
+    status.error = None;
+    status.restart_needed = keep_startup_settings(&config, &mut new_config);
+
+    if (new_config.camera_position, new_config.camera_target)
+        != (config.camera_position, config.camera_target)
+    {
+        if let Some(mut orbit_camera) = orbit_camera {
+            orbit_camera.set_pose(CameraPose::from_config(&new_config));
+        }
+    }
+    if (new_config.grid_offset, new_config.grid_tilt_angle)
+        != (config.grid_offset, config.grid_tilt_angle)
+    {
+        for (grid, mut tfm) in grid_query.iter_mut() {
+            *tfm = grid_transform(grid.side(), &new_config);
+            tfm.translation += grid.player().board_offset();
+        }
+    }
+
+    *config = new_config;
+}
+
+/// Puts back the settings of `old` that can't change while the game runs, and returns the names of those that `new`
+/// tried to change.
+fn keep_startup_settings(old: &Config, new: &mut Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if new.grid_size != old.grid_size {
+        changed.push("grid_size");
+        new.grid_size = old.grid_size;
+    }
+    if new.theme != old.theme {
+        changed.push("theme");
+        new.theme = old.theme.clone();
+    }
+    if new.piece_patterns != old.piece_patterns {
+        changed.push("piece_patterns");
+        new.piece_patterns = old.piece_patterns;
+    }
+    if new.sound_bank != old.sound_bank {
+        changed.push("sound_bank");
+        new.sound_bank = old.sound_bank.clone();
+    }
+
+    changed
+}
+
+/// Stays on top of every screen, and is empty while the config is fine.
+pub fn spawn_config_status(mut commands: Commands) {
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            right: Val::Px(12.0),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                ConfigStatusText,
+                Text::default(),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(1.0, 0.4, 0.3)),
+            ));
+        });
+}
+
+pub fn update_config_status(
+    source: Res<ConfigSource>,
+    status: Res<ConfigStatus>,
+    mut text_query: Query<(&mut Text, Ref<ConfigStatusText>)>,
+) {
+    for (mut text, marker) in text_query.iter_mut() {
+        if !status.is_changed() && !marker.is_added() {
+            continue;
+        }
+        let mut lines = Vec::new();
+        if let Some(error) = &status.error {
+            lines.push(format!(
+                "{} has an error, so the last good config is still in use:\n{}",
+                source.path.display(),
+                error
+            ));
+        }
+        if !status.restart_needed.is_empty() {
+            lines.push(format!(
+                "Restart to apply the new {}.",
+                status.restart_needed.join(", ")
+            ));
+        }
+        text.0 = lines.join("\n");
+    }
+}
@@ -1,41 +1,146 @@
-use crate::{Config, FallingPieceEvent, Rotation};
+use crate::{
+    Autoplay, Board, Config, FallingPieceAction, FallingPieceEvent, GamepadBindings, KeyBindings,
+    Player, Remote, Rotation,
+};
 use bevy::prelude::*;
 
 // TODO: fast drop for one side at a time
 
+/// The buttons that mean the same thing on a keyboard and on a gamepad.
+#[derive(Clone, Copy)]
+enum Input {
+    LeftRotateModifier,
+    LeftTranslateModifier,
+    RightTranslateModifier,
+    RightRotateModifier,
+    MoveLeft,
+    MoveRight,
+    FastDrop,
+}
+
+impl KeyBindings {
+    fn key(&self, input: Input) -> KeyCode {
+        match input {
+            Input::LeftRotateModifier => self.left_rotate_modifier,
+            Input::LeftTranslateModifier => self.left_translate_modifier,
+            Input::RightTranslateModifier => self.right_translate_modifier,
+            Input::RightRotateModifier => self.right_rotate_modifier,
+            Input::MoveLeft => self.move_left,
+            Input::MoveRight => self.move_right,
+            Input::FastDrop => self.fast_drop,
+        }
+    }
+}
+
+impl GamepadBindings {
+    fn button(&self, input: Input) -> GamepadButton {
+        match input {
+            Input::LeftRotateModifier => self.left_rotate_modifier,
+            Input::LeftTranslateModifier => self.left_translate_modifier,
+            Input::RightTranslateModifier => self.right_translate_modifier,
+            Input::RightRotateModifier => self.right_rotate_modifier,
+            Input::MoveLeft => self.move_left,
+            Input::MoveRight => self.move_right,
+            Input::FastDrop => self.fast_drop,
+        }
+    }
+}
+
+/// One player's keyboard bindings and gamepad, if they have one.
+struct PlayerInput<'a> {
+    keyboard: &'a ButtonInput<KeyCode>,
+    keys: &'a KeyBindings,
+    gamepad: Option<&'a Gamepad>,
+    buttons: &'a GamepadBindings,
+}
+
+impl PlayerInput<'_> {
+    fn pressed(&self, input: Input) -> bool {
+        self.keyboard.pressed(self.keys.key(input))
+            || self
+                .gamepad
+                .is_some_and(|g| g.pressed(self.buttons.button(input)))
+    }
+
+    fn just_pressed(&self, input: Input) -> bool {
+        self.keyboard.just_pressed(self.keys.key(input))
+            || self
+                .gamepad
+                .is_some_and(|g| g.just_pressed(self.buttons.button(input)))
+    }
+
+    fn action(&self) -> Option<FallingPieceAction> {
+        if self.just_pressed(Input::FastDrop) {
+            return Some(FallingPieceAction::FastDrop);
+        }
+
+        let left = self.just_pressed(Input::MoveLeft);
+        let right = self.just_pressed(Input::MoveRight);
+        if !left && !right {
+            return None;
+        }
+
+        let action = if self.pressed(Input::LeftRotateModifier) {
+            if left {
+                FallingPieceAction::Rotate(Rotation::rotate_z_neg_90())
+            } else {
+                FallingPieceAction::Rotate(Rotation::rotate_z_pos_90())
+            }
+        } else if self.pressed(Input::RightRotateModifier) {
+            if left {
+                FallingPieceAction::Rotate(Rotation::rotate_x_neg_90())
+            } else {
+                FallingPieceAction::Rotate(Rotation::rotate_x_pos_90())
+            }
+        } else if self.pressed(Input::LeftTranslateModifier) {
+            if left {
+                FallingPieceAction::Translate(IVec3::new(1, 0, 0))
+            } else {
+                FallingPieceAction::Translate(IVec3::new(-1, 0, 0))
+            }
+        } else if self.pressed(Input::RightTranslateModifier) {
+            if left {
+                FallingPieceAction::Translate(IVec3::new(0, 0, -1))
+            } else {
+                FallingPieceAction::Translate(IVec3::new(0, 0, 1))
+            }
+        } else {
+            return None;
+        };
+
+        Some(action)
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn send_move_piece_events(
     config: Res<Config>,
     keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_query: Query<(Entity, &Gamepad)>,
+    board_query: Query<&Player, (With<Board>, Without<Remote>, Without<Autoplay>)>,
     mut events: EventWriter<FallingPieceEvent>,
 ) {
-    if keyboard.just_pressed(KeyCode::Space) {
-        events.send(FallingPieceEvent::FastDrop);
-        return;
-    }
+    let mut gamepads: Vec<(Entity, &Gamepad)> = gamepad_query.iter().collect();
+    gamepads.sort_by_key(|(entity, _)| *entity);
 
-    if keyboard.pressed(config.left_rotate_modifier) {
-        if keyboard.just_pressed(KeyCode::ArrowLeft) {
-            events.send(FallingPieceEvent::Rotate(Rotation::rotate_z_neg_90()));
-        } else if keyboard.just_pressed(KeyCode::ArrowRight) {
-            events.send(FallingPieceEvent::Rotate(Rotation::rotate_z_pos_90()));
-        }
-    } else if keyboard.pressed(config.right_rotate_modifier) {
-        if keyboard.just_pressed(KeyCode::ArrowLeft) {
-            events.send(FallingPieceEvent::Rotate(Rotation::rotate_x_neg_90()));
-        } else if keyboard.just_pressed(KeyCode::ArrowRight) {
-            events.send(FallingPieceEvent::Rotate(Rotation::rotate_x_pos_90()));
-        }
-    } else if keyboard.pressed(config.left_translate_modifier) {
-        if keyboard.just_pressed(KeyCode::ArrowLeft) {
-            events.send(FallingPieceEvent::Translate(IVec3::new(1, 0, 0)));
-        } else if keyboard.just_pressed(KeyCode::ArrowRight) {
-            events.send(FallingPieceEvent::Translate(IVec3::new(-1, 0, 0)));
-        }
-    } else if keyboard.pressed(config.right_translate_modifier) {
-        if keyboard.just_pressed(KeyCode::ArrowLeft) {
-            events.send(FallingPieceEvent::Translate(IVec3::new(0, 0, -1)));
-        } else if keyboard.just_pressed(KeyCode::ArrowRight) {
-            events.send(FallingPieceEvent::Translate(IVec3::new(0, 0, 1)));
+    // Players on this machine take the controls in order, so someone playing online always has player one's controls.
+    // Boards played by the bot don't need any.
+    let mut local_players: Vec<Player> = board_query.iter().cloned().collect();
+    local_players.sort();
+    for (i, player) in local_players.into_iter().enumerate() {
+        let keys = if i == 0 {
+            &config.player_one_keys
+        } else {
+            &config.player_two_keys
+        };
+        let input = PlayerInput {
+            keyboard: &keyboard,
+            keys,
+            gamepad: gamepads.get(i).map(|(_, g)| *g),
+            buttons: &config.gamepad_buttons,
+        };
+        if let Some(action) = input.action() {
+            events.send(FallingPieceEvent::new(player, action));
         }
     }
 }
@@ -1,9 +1,7 @@
-use crate::FallingPieceEvent;
+use crate::{Board, FallingPieceAction, FallingPieceEvent, Grid, LevelUp, Player};
 use bevy::prelude::*;
 
-// TODO: get faster as the player's score gets higher
-
-#[derive(Resource)]
+#[derive(Component)]
 pub struct DropTimer {
     last_drop_time: f64,
     time_between_drops: f64,
@@ -17,6 +15,19 @@ impl DropTimer {
         }
     }
 
+    pub fn set_time_between_drops(&mut self, time_between_drops: f64) {
+        self.time_between_drops = time_between_drops;
+    }
+
+    /// How far along the timer is, so that a saved game can pick up where it left off.
+    pub fn secs_since_last_drop(&self, time: &Time) -> f64 {
+        time.elapsed_secs_f64() - self.last_drop_time
+    }
+
+    pub fn set_secs_since_last_drop(&mut self, secs: f64, time: &Time) {
+        self.last_drop_time = time.elapsed_secs_f64() - secs;
+    }
+
     pub fn should_drop(&mut self, time: &Time) -> bool {
         let now = time.elapsed_secs_f64();
         if now - self.last_drop_time > self.time_between_drops {
@@ -29,12 +40,38 @@ impl DropTimer {
     }
 }
 
+/// Each level makes the piece fall 15% faster than the last, up to a limit.
+pub fn time_between_drops_for_level(level: u32) -> f64 {
+    const FIRST_LEVEL_TIME: f64 = 0.75;
+    const MIN_TIME: f64 = 0.05;
+
+    (FIRST_LEVEL_TIME * 0.85f64.powi(level.saturating_sub(1) as i32)).max(MIN_TIME)
+}
+
+pub fn speed_up_drop_timer(
+    mut events: EventReader<LevelUp>,
+    mut board_query: Query<(&Player, &mut DropTimer), With<Board>>,
+) {
+    for LevelUp { player, level } in events.read() {
+        for (_, mut drop_timer) in board_query.iter_mut().filter(|(p, _)| *p == player) {
+            drop_timer.set_time_between_drops(time_between_drops_for_level(*level));
+        }
+    }
+}
+
+/// Gravity is paused for a player while their rows are being cleared.
 pub fn send_drop_piece_events(
     mut movement_events: EventWriter<FallingPieceEvent>,
     time: Res<Time>,
-    mut drop_timer: ResMut<DropTimer>,
+    grid_query: Query<&Grid>,
+    mut board_query: Query<(&Player, &mut DropTimer), With<Board>>,
 ) {
-    if drop_timer.should_drop(&time) {
-        movement_events.send(FallingPieceEvent::Drop);
+    for (player, mut drop_timer) in board_query.iter_mut() {
+        let clearing_rows = grid_query
+            .iter()
+            .any(|g| g.player() == *player && g.has_full_rows());
+        if !clearing_rows && drop_timer.should_drop(&time) {
+            movement_events.send(FallingPieceEvent::new(*player, FallingPieceAction::Drop));
+        }
     }
 }
@@ -0,0 +1,321 @@
+use crate::{
+    move_accepted_in_grid, CellValue, FallingPiece, FallingPieceAction, GameStats, GrabBag, Grid,
+    GridSide, PieceState, PieceType, Player, Rotation,
+};
+use bevy::prelude::*;
+
+/// The moves an agent can make in an `Environment`, the same ones the controls can make.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Action {
+    Drop,
+    FastDrop,
+    TranslateXPos,
+    TranslateXNeg,
+    TranslateZPos,
+    TranslateZNeg,
+    RotateXPos,
+    RotateXNeg,
+    RotateZPos,
+    RotateZNeg,
+}
+
+pub const ALL_ACTIONS: [Action; 10] = [
+    Action::Drop,
+    Action::FastDrop,
+    Action::TranslateXPos,
+    Action::TranslateXNeg,
+    Action::TranslateZPos,
+    Action::TranslateZNeg,
+    Action::RotateXPos,
+    Action::RotateXNeg,
+    Action::RotateZPos,
+    Action::RotateZNeg,
+];
+
+impl Action {
+    pub fn falling_piece_action(&self) -> FallingPieceAction {
+        match self {
+            Action::Drop => FallingPieceAction::Drop,
+            Action::FastDrop => FallingPieceAction::FastDrop,
+            Action::TranslateXPos => FallingPieceAction::Translate(IVec3::X),
+            Action::TranslateXNeg => FallingPieceAction::Translate(IVec3::NEG_X),
+            Action::TranslateZPos => FallingPieceAction::Translate(IVec3::Z),
+            Action::TranslateZNeg => FallingPieceAction::Translate(IVec3::NEG_Z),
+            Action::RotateXPos => FallingPieceAction::Rotate(Rotation::rotate_x_pos_90()),
+            Action::RotateXNeg => FallingPieceAction::Rotate(Rotation::rotate_x_neg_90()),
+            Action::RotateZPos => FallingPieceAction::Rotate(Rotation::rotate_z_pos_90()),
+            Action::RotateZNeg => FallingPieceAction::Rotate(Rotation::rotate_z_neg_90()),
+        }
+    }
+
+    /// The action that does the same thing, e.g. to play an `Agent`'s path. `None` for anything the controls can't do.
+    pub fn from_falling_piece_action(action: &FallingPieceAction) -> Option<Self> {
+        ALL_ACTIONS
+            .iter()
+            .cloned()
+            .find(|a| match (a.falling_piece_action(), action) {
+                (FallingPieceAction::Drop, FallingPieceAction::Drop)
+                | (FallingPieceAction::FastDrop, FallingPieceAction::FastDrop) => true,
+                (FallingPieceAction::Translate(t1), FallingPieceAction::Translate(t2)) => t1 == *t2,
+                (FallingPieceAction::Rotate(r1), FallingPieceAction::Rotate(r2)) => {
+                    r1.matrix == r2.matrix
+                }
+                _ => false,
+            })
+    }
+}
+
+/// What an agent gets to see after every step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    /// Which cells of each grid are filled, row by row from the bottom like `Grid::visible_cells`. The falling piece
+    /// isn't included.
+    pub left: Vec<bool>,
+    pub right: Vec<bool>,
+    pub piece_type: PieceType,
+    pub piece_position: IVec3,
+    /// Where the piece's other cubes are relative to its center.
+    pub piece_orientation: [IVec3; 3],
+}
+
+/// What happened during a step, besides the reward.
+#[derive(Clone, Debug, Default)]
+pub struct StepInfo {
+    /// False if the piece couldn't make the move, e.g. because it was against a wall.
+    pub accepted: bool,
+    pub placed: bool,
+    /// Indexed like `Environment::grids`.
+    pub lines_cleared: [u32; 2],
+}
+
+/// A single player game with no rendering, no entities and no clock, for training agents. Every step plays one action,
+/// and the piece drops a row on its own every `steps_per_drop` steps, like the drop timer would. The reward is the
+/// points the step scored, and the episode is done when the next piece has no room to spawn.
+pub struct Environment {
+    grid_size: [usize; 2],
+    repeats_per_bag: usize,
+    steps_per_drop: u32,
+    grids: [Grid; 2],
+    grab_bag: GrabBag,
+    state: PieceState,
+    stats: GameStats,
+    steps: u32,
+    steps_since_drop: u32,
+    done: bool,
+}
+
+impl Environment {
+    /// Starts with seed 0. Call `reset` for a different sequence of pieces.
+    pub fn new(grid_size: [usize; 2], repeats_per_bag: usize, steps_per_drop: u32) -> Self {
+        let grab_bag = GrabBag::new(repeats_per_bag, 0);
+        let mut env = Self {
+            grid_size,
+            repeats_per_bag,
+            steps_per_drop: steps_per_drop.max(1),
+            grids: [
+                Grid::headless(GridSide::Left, Player::One, grid_size),
+                Grid::headless(GridSide::Right, Player::One, grid_size),
+            ],
+            grab_bag,
+            state: PieceState::new(FallingPiece::at_spawn(
+                PieceType::Stick,
+                Player::One,
+                grid_size,
+            )),
+            stats: GameStats::default(),
+            steps: 0,
+            steps_since_drop: 0,
+            done: false,
+        };
+        env.reset(0);
+
+        env
+    }
+
+    /// Starts a new episode. The same seed always produces the same sequence of pieces.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        for grid in self.grids.iter_mut() {
+            grid.fill_master(|_| CellValue::Empty);
+            grid.activate();
+        }
+        self.grab_bag = GrabBag::new(self.repeats_per_bag, seed);
+        self.stats = GameStats::default();
+        self.steps = 0;
+        self.done = false;
+        self.spawn_next_piece();
+
+        self.observation()
+    }
+
+    /// Returns the observation, the reward, whether the episode is done, and what else happened. Steps after the
+    /// episode is done do nothing.
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool, StepInfo) {
+        let mut info = StepInfo::default();
+        if self.done {
+            return (self.observation(), 0.0, true, info);
+        }
+
+        self.steps += 1;
+        let [left, right] = &self.grids;
+        info.accepted = self
+            .state
+            .apply(&action.falling_piece_action(), [left, right]);
+
+        self.steps_since_drop += 1;
+        if self.steps_since_drop >= self.steps_per_drop {
+            self.state.apply(&FallingPieceAction::Drop, [left, right]);
+            self.steps_since_drop = 0;
+        }
+
+        let mut reward = 0.0;
+        if self.state.is_placed() {
+            info.placed = true;
+            reward = self.lock_piece(&mut info.lines_cleared) as f32;
+            self.spawn_next_piece();
+        }
+
+        (self.observation(), reward, self.done, info)
+    }
+
+    pub fn observation(&self) -> Observation {
+        let occupancy = |grid: &Grid| grid.visible_cells().iter().map(|c| c.is_filled()).collect();
+        let piece = &self.state.piece;
+
+        Observation {
+            left: occupancy(&self.grids[0]),
+            right: occupancy(&self.grids[1]),
+            piece_type: piece.piece_type(),
+            piece_position: piece.center_position(),
+            piece_orientation: piece.offsets(),
+        }
+    }
+
+    /// The left grid, then the right grid.
+    pub fn grids(&self) -> &[Grid; 2] {
+        &self.grids
+    }
+
+    pub fn piece(&self) -> &FallingPiece {
+        &self.state.piece
+    }
+
+    pub fn stats(&self) -> &GameStats {
+        &self.stats
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Writes the piece into both grids and clears full rows, the way the game does. Returns the points scored.
+    fn lock_piece(&mut self, lines_cleared: &mut [u32; 2]) -> u64 {
+        let mut clears = Vec::new();
+        for (i, grid) in self.grids.iter_mut().enumerate() {
+            let Some(locked) = self.state.locked[i] else {
+                continue;
+            };
+            grid.write_piece(&locked);
+            grid.deactivate();
+            let count = grid.take_unreported_full_rows().len();
+            if count > 0 {
+                grid.collapse_full_rows();
+                clears.push((grid.side(), count));
+                lines_cleared[i] = count as u32;
+            }
+        }
+        self.stats.add_placed_piece();
+
+        self.stats.add_clears(&clears)
+    }
+
+    fn spawn_next_piece(&mut self) {
+        let piece_type = self.grab_bag.choose_next_piece_type();
+        let piece = FallingPiece::at_spawn(piece_type, Player::One, self.grid_size);
+        self.state = PieceState::new(piece);
+        self.steps_since_drop = 0;
+        for grid in self.grids.iter_mut() {
+            grid.activate();
+        }
+
+        // The stacks have reached the top, so the episode is over.
+        self.done = !self.grids.iter().all(|g| move_accepted_in_grid(&piece, g));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID_SIZE: [usize; 2] = [10, 20];
+
+    /// Plays `actions` from a reset, returning everything the agent would see.
+    fn play(
+        env: &mut Environment,
+        seed: u64,
+        actions: &[Action],
+    ) -> Vec<(Observation, f32, bool, [u32; 2])> {
+        env.reset(seed);
+        actions
+            .iter()
+            .map(|action| {
+                let (observation, reward, done, info) = env.step(*action);
+                (observation, reward, done, info.lines_cleared)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn episodes_with_the_same_seed_and_actions_are_identical() {
+        let actions: Vec<Action> = (0..500)
+            .map(|i| ALL_ACTIONS[(i * 7 + i / 3) % 10])
+            .collect();
+        let mut env = Environment::new(GRID_SIZE, 1, 3);
+
+        let first = play(&mut env, 42, &actions);
+        assert!(env.stats().pieces_placed() > 1);
+        let second = play(&mut env, 42, &actions);
+        assert_eq!(first, second);
+
+        let other_seed = play(&mut env, 43, &actions);
+        assert_ne!(first, other_seed);
+    }
+
+    /// Fast drops the piece until it's placed.
+    fn place_piece(env: &mut Environment) -> (f32, StepInfo) {
+        for _ in 0..GRID_SIZE[1] {
+            let (_, reward, _, info) = env.step(Action::FastDrop);
+            if info.placed {
+                return (reward, info);
+            }
+        }
+        panic!("the piece was never placed");
+    }
+
+    #[test]
+    fn filling_a_row_clears_it_and_scores() {
+        let width = GRID_SIZE[0];
+        let mut env = Environment::new(GRID_SIZE, 1, 3);
+        env.reset(7);
+        place_piece(&mut env);
+        // The cells of the bottom row that the first piece fills on its own.
+        let landed: Vec<bool> = env.observation().left[..width].to_vec();
+        assert!(landed.contains(&true));
+
+        env.reset(7);
+        env.grids[0].fill_master(|p| {
+            if p.y == 0 && !landed[p.x as usize] {
+                CellValue::Garbage
+            } else {
+                CellValue::Empty
+            }
+        });
+        let (reward, info) = place_piece(&mut env);
+        assert_eq!(info.lines_cleared, [1, 0]);
+        assert!(reward > 0.0);
+        assert_eq!(env.stats().lines(GridSide::Left), 1);
+    }
+}
@@ -1,21 +1,47 @@
 use crate::{
-    CellValue, Config, GrabBag, Grid, PieceCollisionResult, PieceMaterials, PieceType, Rotation,
-    SceneAssets,
+    rotate_offset, spawn_ghost_piece, Board, CellValue, Config, GrabBag, Grid, GridSide,
+    PieceCollisionResult, PieceMaterials, PieceMotion, PieceType, Player, Rotation, SceneAssets,
 };
 use bevy::prelude::*;
 
-#[derive(Clone, Component, Copy, Debug)]
+#[derive(Clone, Component, Copy, Debug, serde::Deserialize, serde::Serialize)]
 pub struct FallingPiece {
     piece_type: PieceType,
+    player: Player,
     center_position: IVec3,
     offsets: [IVec3; 3],
 }
 
 impl FallingPiece {
+    /// A new piece at the top of the grids, where every piece starts.
+    pub fn at_spawn(piece_type: PieceType, player: Player, grid_shape: [usize; 2]) -> Self {
+        let [shape_x, shape_y] = grid_shape;
+
+        Self {
+            piece_type,
+            player,
+            center_position: IVec3::new(shape_x as i32 / 2, shape_y as i32 - 1, shape_x as i32 / 2),
+            offsets: piece_type.cube_configuration().map(Into::into),
+        }
+    }
+
     pub fn piece_type(&self) -> PieceType {
         self.piece_type
     }
 
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    pub fn center_position(&self) -> IVec3 {
+        self.center_position
+    }
+
+    /// Where the other cubes are relative to the center, which is how the piece's orientation is stored.
+    pub fn offsets(&self) -> [IVec3; 3] {
+        self.offsets
+    }
+
     pub fn translate(&mut self, offset: IVec3) {
         self.center_position += offset;
     }
@@ -25,12 +51,8 @@ impl FallingPiece {
     }
 
     pub fn rotate(&mut self, matrix: [[i32; 3]; 3]) {
-        let x_map = IVec3::from(matrix[0]);
-        let y_map = IVec3::from(matrix[1]);
-        let z_map = IVec3::from(matrix[2]);
-
         for p in self.offsets.iter_mut() {
-            *p = IVec3::new(x_map.dot(*p), y_map.dot(*p), z_map.dot(*p));
+            *p = rotate_offset(matrix, *p);
         }
     }
 
@@ -45,32 +67,58 @@ impl FallingPiece {
 }
 
 pub fn spawn_falling_piece(
+    player: Player,
     grid_shape: [usize; 2],
     grab_bag: &mut GrabBag,
     materials: &PieceMaterials,
     cube_mesh: Handle<Mesh>,
     grid_query: &mut Query<&mut Grid>,
     commands: &mut Commands,
-) -> Entity {
-    let [shape_x, shape_y] = grid_shape;
-    let center_position = IVec3::new(shape_x as i32 / 2, shape_y as i32 - 1, shape_x as i32 / 2);
+) -> Option<Entity> {
     let piece_type = grab_bag.choose_next_piece_type();
-    let child_cubes = piece_type.cube_configuration();
+    let piece = FallingPiece::at_spawn(piece_type, player, grid_shape);
+
+    // The stacks have reached the top, so the game is over.
+    let spawn_blocked = grid_query
+        .iter()
+        .filter(|g| g.player() == player)
+        .any(|g| !move_accepted_in_grid(&piece, g));
+    if spawn_blocked {
+        return None;
+    }
+
+    for mut grid in grid_query.iter_mut().filter(|g| g.player() == player) {
+        grid.activate();
+        grid.write_piece(&piece);
+    }
+
+    write_drop_hint_in_active_grids(&piece, grid_query);
 
+    Some(spawn_piece_entity(piece, materials, cube_mesh, commands))
+}
+
+/// The cubes of `piece`, wherever it is and however it's turned. This doesn't touch the grids.
+pub fn spawn_piece_entity(
+    piece: FallingPiece,
+    materials: &PieceMaterials,
+    cube_mesh: Handle<Mesh>,
+    commands: &mut Commands,
+) -> Entity {
+    let piece_type = piece.piece_type;
     let center_cube = cube_pbr(
         piece_type,
-        // Offset by 0.5 because the cube is centered at 0.
-        center_position.as_vec3() + Vec3::splat(0.5),
+        cube_translation(piece.player, piece.center_position),
         materials,
         cube_mesh.clone(),
     );
-    let child_cube_entities: Vec<Entity> = child_cubes
+    let child_cube_entities: Vec<Entity> = piece
+        .offsets
         .iter()
         .map(|cube_offset| {
             commands
                 .spawn(cube_pbr(
                     piece_type,
-                    IVec3::from(*cube_offset).as_vec3(),
+                    cube_offset.as_vec3(),
                     materials,
                     cube_mesh.clone(),
                 ))
@@ -78,28 +126,25 @@ pub fn spawn_falling_piece(
         })
         .collect();
 
-    let piece = FallingPiece {
-        piece_type,
-        center_position,
-        offsets: child_cubes.map(Into::into),
-    };
-
-    for mut grid in grid_query.iter_mut() {
-        grid.activate();
-        grid.write_piece(&piece);
-    }
-
-    write_drop_hint_in_active_grids(&piece, grid_query);
+    let ghost = spawn_ghost_piece(&piece, materials, cube_mesh, commands);
 
     commands
         .spawn_empty()
         .insert(piece)
+        .insert(PieceMotion::new(center_cube.2))
         .insert(center_cube)
         .add_children(&child_cube_entities)
+        .add_child(ghost)
         .id()
 }
 
-fn cube_pbr(
+/// Where a cube at `p` in a player's grids goes in the world. It's offset by 0.5 because the cube mesh is centered
+/// at 0.
+pub fn cube_translation(player: Player, p: IVec3) -> Vec3 {
+    player.board_offset() + p.as_vec3() + Vec3::splat(0.5)
+}
+
+pub fn cube_pbr(
     piece_type: PieceType,
     offset: Vec3,
     materials: &PieceMaterials,
@@ -112,8 +157,21 @@ fn cube_pbr(
     )
 }
 
+/// Asks for something to happen to one player's falling piece.
 #[derive(Clone, Debug, Event)]
-pub enum FallingPieceEvent {
+pub struct FallingPieceEvent {
+    pub player: Player,
+    pub action: FallingPieceAction,
+}
+
+impl FallingPieceEvent {
+    pub fn new(player: Player, action: FallingPieceAction) -> Self {
+        Self { player, action }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FallingPieceAction {
     Spawn,
     Drop,
     FastDrop,
@@ -121,23 +179,75 @@ pub enum FallingPieceEvent {
     Rotate(Rotation),
 }
 
+/// Sent when the game ends, either because there is no room to spawn the next piece or because the game mode decided
+/// so.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct GameOver {
+    /// The player whose board ended the game.
+    pub player: Player,
+    pub reason: GameOverReason,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Resource)]
+pub enum GameOverReason {
+    ToppedOut,
+    Completed,
+    /// Some modes only give the player a limited number of pieces.
+    OutOfPieces,
+    /// The opponent or the server went away in the middle of an online game.
+    Disconnected,
+}
+
+/// Reports what actually happened to a player's falling piece while handling a `FallingPieceEvent`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Event)]
+pub struct FallingPieceFeedback {
+    pub player: Player,
+    pub outcome: FallingPieceOutcome,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FallingPieceOutcome {
+    Translated,
+    Rotated,
+    RotationRejected,
+    Locked(GridSide),
+    /// The piece has locked in every grid.
+    Placed,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn update_falling_piece(
     mut commands: Commands,
     mut events: EventReader<FallingPieceEvent>,
-    mut falling_piece_query: Query<(Entity, &mut FallingPiece, &mut Transform)>,
+    mut feedback: EventWriter<FallingPieceFeedback>,
+    mut game_over: EventWriter<GameOver>,
+    mut falling_piece_query: Query<(Entity, &mut FallingPiece, &mut PieceMotion)>,
     mut grid_query: Query<&mut Grid>,
-    mut grab_bag: ResMut<GrabBag>,
+    mut board_query: Query<(&Player, &mut GrabBag), With<Board>>,
     scene_assets: Res<SceneAssets>,
     config: Res<Config>,
 ) {
-    for event in events.read() {
-        // Reset the visible copy of the grid.
-        for mut grid in grid_query.iter_mut() {
+    // Once a player's piece has been spawned or despawned, the rest of their events this frame are dropped. They don't
+    // apply to the new piece, and the stale entity of a despawned piece still exists in our query.
+    let mut settled_players = Vec::new();
+
+    for FallingPieceEvent { player, action } in events.read() {
+        let player = *player;
+        if settled_players.contains(&player) {
+            continue;
+        }
+        let Some((_, mut grab_bag)) = board_query.iter_mut().find(|(p, _)| **p == player) else {
+            continue;
+        };
+
+        // Reset the visible copy of the player's grids.
+        for mut grid in grid_query.iter_mut().filter(|g| g.player() == player) {
             grid.copy_master_to_visible();
         }
 
-        if let FallingPieceEvent::Spawn = event {
-            spawn_falling_piece(
+        if let FallingPieceAction::Spawn = action {
+            let spawned = spawn_falling_piece(
+                player,
                 config.grid_size,
                 &mut grab_bag,
                 &scene_assets.piece_materials,
@@ -145,51 +255,122 @@ pub fn update_falling_piece(
                 &mut grid_query,
                 &mut commands,
             );
-            return;
+            if spawned.is_none() {
+                game_over.send(GameOver {
+                    player,
+                    reason: GameOverReason::ToppedOut,
+                });
+            }
+            settled_players.push(player);
+            continue;
         }
 
-        for (piece_entity, mut piece, mut tfm) in falling_piece_query.iter_mut() {
-            match event.clone() {
-                FallingPieceEvent::Drop => try_drop_piece(&mut piece, &mut tfm, &mut grid_query),
-                FallingPieceEvent::FastDrop => {
-                    fast_drop_piece(&mut piece, &mut tfm, &mut grid_query)
+        let send_feedback = |feedback: &mut EventWriter<FallingPieceFeedback>, outcome| {
+            feedback.send(FallingPieceFeedback { player, outcome });
+        };
+
+        for (piece_entity, mut piece, mut motion) in falling_piece_query.iter_mut() {
+            if piece.player != player {
+                continue;
+            }
+            let active_sides_before = active_grid_sides(player, &grid_query);
+
+            match action.clone() {
+                FallingPieceAction::Drop => {
+                    try_drop_piece(&mut piece, &mut motion, &mut grid_query)
+                }
+                FallingPieceAction::FastDrop => {
+                    fast_drop_piece(&mut piece, &mut motion, &mut grid_query)
                 }
-                FallingPieceEvent::Rotate(rotation) => {
-                    try_rotate_piece(rotation, &mut piece, &mut tfm, &mut grid_query)
+                FallingPieceAction::Rotate(rotation) => {
+                    if try_rotate_piece(rotation, &mut piece, &mut motion, &mut grid_query) {
+                        send_feedback(&mut feedback, FallingPieceOutcome::Rotated);
+                    } else {
+                        send_feedback(&mut feedback, FallingPieceOutcome::RotationRejected);
+                    }
                 }
-                FallingPieceEvent::Translate(translation) => {
-                    try_translate_piece(translation, &mut piece, &mut tfm, &mut grid_query)
+                FallingPieceAction::Translate(translation) => {
+                    if try_translate_piece(translation, &mut piece, &mut motion, &mut grid_query) {
+                        send_feedback(&mut feedback, FallingPieceOutcome::Translated);
+                    }
                 }
-                FallingPieceEvent::Spawn => {
+                FallingPieceAction::Spawn => {
                     unreachable!()
                 }
             }
 
+            let active_sides_after = active_grid_sides(player, &grid_query);
+            for side in active_sides_before {
+                if !active_sides_after.contains(&side) {
+                    send_feedback(&mut feedback, FallingPieceOutcome::Locked(side));
+                }
+            }
+
             write_piece_to_active_grids(&piece, &mut grid_query);
 
-            let any_active_grids = grid_query.iter_mut().any(|g| g.is_active());
+            let any_active_grids = grid_query
+                .iter()
+                .any(|g| g.player() == player && g.is_active());
             if any_active_grids {
                 write_drop_hint_in_active_grids(&piece, &mut grid_query);
             } else {
                 commands.entity(piece_entity).despawn_recursive();
-                spawn_falling_piece(
-                    config.grid_size,
-                    &mut grab_bag,
-                    &scene_assets.piece_materials,
-                    scene_assets.cube_mesh.clone(),
-                    &mut grid_query,
-                    &mut commands,
-                );
-                // This return is important. It's possible that there are more events to be processed, but they don't apply to
-                // the piece entity we just despawned. That stale entity still exists in our query.
-                return;
+                send_feedback(&mut feedback, FallingPieceOutcome::Placed);
+                // If any rows were cleared, the next piece is spawned once the line clear animation finishes.
+                let any_full_rows = grid_query
+                    .iter()
+                    .any(|g| g.player() == player && g.has_full_rows());
+                if !any_full_rows {
+                    let spawned = spawn_falling_piece(
+                        player,
+                        config.grid_size,
+                        &mut grab_bag,
+                        &scene_assets.piece_materials,
+                        scene_assets.cube_mesh.clone(),
+                        &mut grid_query,
+                        &mut commands,
+                    );
+                    if spawned.is_none() {
+                        game_over.send(GameOver {
+                            player,
+                            reason: GameOverReason::ToppedOut,
+                        });
+                    }
+                }
+                settled_players.push(player);
             }
+            break;
         }
     }
 }
 
+/// Redraws the falling piece and its drop hints after the grids have changed underneath it.
+pub fn redraw_falling_piece(piece: &FallingPiece, grid_query: &mut Query<&mut Grid>) {
+    for mut grid in grid_query.iter_mut().filter(|g| g.player() == piece.player) {
+        grid.copy_master_to_visible();
+    }
+    write_piece_to_active_grids(piece, grid_query);
+    write_drop_hint_in_active_grids(piece, grid_query);
+}
+
+/// Whether the piece fits in every one of its player's grids, e.g. after the stacks have been pushed up underneath it.
+pub fn piece_fits(piece: &FallingPiece, grid_query: &Query<&mut Grid>) -> bool {
+    grid_query
+        .iter()
+        .filter(|g| g.player() == piece.player)
+        .all(|g| move_accepted_in_grid(piece, g))
+}
+
+fn active_grid_sides(player: Player, grid_query: &Query<&mut Grid>) -> Vec<GridSide> {
+    grid_query
+        .iter()
+        .filter(|g| g.player() == player && g.is_active())
+        .map(|g| g.side())
+        .collect()
+}
+
 fn write_piece_to_active_grids(piece: &FallingPiece, grid_query: &mut Query<&mut Grid>) {
-    for mut grid in grid_query.iter_mut() {
+    for mut grid in grid_query.iter_mut().filter(|g| g.player() == piece.player) {
         if grid.is_active() {
             grid.write_piece(piece);
         }
@@ -197,7 +378,7 @@ fn write_piece_to_active_grids(piece: &FallingPiece, grid_query: &mut Query<&mut
 }
 
 fn write_drop_hint_in_active_grids(piece: &FallingPiece, grid_query: &mut Query<&mut Grid>) {
-    for mut grid in grid_query.iter_mut() {
+    for mut grid in grid_query.iter_mut().filter(|g| g.player() == piece.player) {
         if grid.is_active() {
             let dropped_piece = speculate_fast_drop_piece(piece, &grid);
             grid.write_piece_with_value(&dropped_piece, CellValue::DropHint);
@@ -207,28 +388,29 @@ fn write_drop_hint_in_active_grids(piece: &FallingPiece, grid_query: &mut Query<
 
 fn try_drop_piece(
     piece: &mut FallingPiece,
-    tfm: &mut Transform,
+    motion: &mut PieceMotion,
     grid_query: &mut Query<&mut Grid>,
 ) {
     let mut new_piece = *piece;
     new_piece.translate_n_rows(-1);
 
     if move_accepted_in_all_active_grids(piece, &new_piece, true, grid_query) {
-        tfm.translation -= Vec3::Y;
+        motion.translate(Vec3::NEG_Y);
         *piece = new_piece;
     }
 }
 
 fn fast_drop_piece(
     piece: &mut FallingPiece,
-    tfm: &mut Transform,
+    motion: &mut PieceMotion,
     grid_query: &mut Query<&mut Grid>,
 ) {
     let rows_dropped = fast_drop_piece_in_all_active_grids(piece, true, grid_query);
-    tfm.translation -= rows_dropped as f32 * Vec3::Y;
+    motion.translate(rows_dropped as f32 * Vec3::NEG_Y);
 }
 
-fn speculate_fast_drop_piece(piece: &FallingPiece, grid: &Grid) -> FallingPiece {
+/// Where the piece would lock in the grid if it fell straight down from where it is.
+pub fn speculate_fast_drop_piece(piece: &FallingPiece, grid: &Grid) -> FallingPiece {
     let mut moved_piece = *piece;
     fast_drop_piece_in_grid(&mut moved_piece, grid);
 
@@ -251,7 +433,9 @@ fn fast_drop_piece_in_all_active_grids(
             *piece = new_piece;
         }
 
-        let any_grids_active = grid_query.iter_mut().any(|g| g.is_active());
+        let any_grids_active = grid_query
+            .iter()
+            .any(|g| g.player() == piece.player && g.is_active());
         if !any_grids_active {
             break;
         }
@@ -273,31 +457,37 @@ fn fast_drop_piece_in_grid(piece: &mut FallingPiece, grid: &Grid) {
 fn try_rotate_piece(
     rotation: Rotation,
     piece: &mut FallingPiece,
-    tfm: &mut Transform,
+    motion: &mut PieceMotion,
     grid_query: &mut Query<&mut Grid>,
-) {
+) -> bool {
     let mut new_piece = *piece;
     new_piece.rotate(rotation.matrix);
 
-    if move_accepted_in_all_active_grids(piece, &new_piece, false, grid_query) {
-        tfm.rotation = rotation.quat * tfm.rotation;
+    let accepted = move_accepted_in_all_active_grids(piece, &new_piece, false, grid_query);
+    if accepted {
+        motion.rotate(rotation.quat);
         *piece = new_piece;
     }
+
+    accepted
 }
 
 fn try_translate_piece(
     translation: IVec3,
     piece: &mut FallingPiece,
-    tfm: &mut Transform,
+    motion: &mut PieceMotion,
     grid_query: &mut Query<&mut Grid>,
-) {
+) -> bool {
     let mut new_piece = *piece;
     new_piece.translate(translation);
 
-    if move_accepted_in_all_active_grids(piece, &new_piece, false, grid_query) {
-        tfm.translation += translation.as_vec3();
+    let accepted = move_accepted_in_all_active_grids(piece, &new_piece, false, grid_query);
+    if accepted {
+        motion.translate(translation.as_vec3());
         *piece = new_piece;
     }
+
+    accepted
 }
 
 fn move_accepted_in_all_active_grids(
@@ -307,7 +497,10 @@ fn move_accepted_in_all_active_grids(
     grid_query: &mut Query<&mut Grid>,
 ) -> bool {
     let mut move_accepted_in_all_active_grids = true;
-    for mut grid in grid_query.iter_mut() {
+    for mut grid in grid_query
+        .iter_mut()
+        .filter(|g| g.player() == new_piece.player)
+    {
         if !grid.is_active() {
             continue;
         }
@@ -324,7 +517,8 @@ fn move_accepted_in_all_active_grids(
     move_accepted_in_all_active_grids
 }
 
-fn move_accepted_in_grid(new_piece: &FallingPiece, grid: &Grid) -> bool {
+/// Whether the piece fits in the grid without leaving it or overlapping anything.
+pub fn move_accepted_in_grid(new_piece: &FallingPiece, grid: &Grid) -> bool {
     match grid.check_piece_collision(new_piece) {
         PieceCollisionResult::NoCollision => true,
         PieceCollisionResult::OutOfBounds | PieceCollisionResult::HitOtherPiece => false,
@@ -1,29 +1,165 @@
-use crate::{create_grids, Config, DropTimer, FallingPieceEvent, GrabBag, SceneAssets};
-use bevy::prelude::*;
+use crate::{
+    create_grids, time_between_drops_for_level, ActivePuzzle, Config, DropTimer, FallingPiece,
+    FallingPieceAction, FallingPieceEvent, GameClock, GameMode, GameOverPlayer, GameOverReason,
+    GameStats, GrabBag, Grid, Hud, OnlineGame, PendingClears, PersonalBest, Player, Remote,
+    SceneAssets, SnapshotPiece, ALL_PLAYERS,
+};
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
 
+/// Holds the state of one player's game. The player's grids and falling piece are separate entities, tagged with the
+/// same `Player`.
+#[derive(Component)]
+pub struct Board;
+
+/// Plays every game with the same seed, from `--seed` or a replay, instead of a random one.
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct FixedSeed(pub u64);
+
+/// The seed of the game in progress. It picks the pieces and the garbage holes of every board.
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct GameSeed(pub u64);
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_game(
     config: Res<Config>,
+    mode: Res<GameMode>,
     time: Res<Time>,
+    real_time: Res<Time<Real>>,
     scene_assets: Res<SceneAssets>,
+    online: Option<Res<OnlineGame>>,
+    fixed_seed: Option<Res<FixedSeed>>,
     mut commands: Commands,
     mut piece_events: EventWriter<FallingPieceEvent>,
 ) {
-    create_grids(&config, &scene_assets, &mut commands);
-    create_camera(&config, &mut commands);
+    // Every player gets the same sequence of pieces, even when they're on different machines.
+    let seed = match (&online, fixed_seed) {
+        (Some(online), _) => online.seed,
+        (None, Some(fixed_seed)) => fixed_seed.0,
+        (None, None) => rand::random(),
+    };
+    commands.insert_resource(GameSeed(seed));
+    commands.insert_resource(GameClock::start(&real_time, 0.0));
+    for player in mode.players().iter().cloned() {
+        create_grids(
+            player,
+            config.grid_size,
+            &config,
+            &scene_assets,
+            &mut commands,
+        );
+
+        let stats = GameStats::default();
+        if online
+            .as_ref()
+            .is_some_and(|online| online.local_player != player)
+        {
+            commands.spawn((Board, player, stats, Remote::default()));
+            continue;
+        }
+        commands.spawn((
+            Board,
+            player,
+            DropTimer::new(time_between_drops_for_level(stats.level()), &time),
+            GrabBag::new(config.repeats_per_bag, seed),
+            PendingClears::default(),
+            stats,
+        ));
+
+        piece_events.send(FallingPieceEvent::new(player, FallingPieceAction::Spawn));
+    }
+}
+
+/// Run condition so `create_game` only runs when there isn't already a game in progress, e.g. when resuming from pause.
+pub fn game_exists(grid_query: Query<(), With<Grid>>) -> bool {
+    !grid_query.is_empty()
+}
 
-    commands.insert_resource(DropTimer::new(0.75, &time));
-    commands.insert_resource(GrabBag::new(config.repeats_per_bag));
+/// Undoes `create_game` so that a new game can be created.
+pub fn teardown_game(
+    mut commands: Commands,
+    grid_query: Query<Entity, With<Grid>>,
+    falling_piece_query: Query<Entity, With<FallingPiece>>,
+    hud_query: Query<Entity, With<Hud>>,
+    board_query: Query<Entity, With<Board>>,
+    snapshot_piece_query: Query<Entity, With<SnapshotPiece>>,
+    mut piece_events: ResMut<Events<FallingPieceEvent>>,
+) {
+    for entity in grid_query
+        .iter()
+        .chain(falling_piece_query.iter())
+        .chain(hud_query.iter())
+        .chain(board_query.iter())
+        .chain(snapshot_piece_query.iter())
+    {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<GameOverReason>();
+    commands.remove_resource::<PersonalBest>();
+    commands.remove_resource::<ActivePuzzle>();
+    commands.remove_resource::<GameOverPlayer>();
 
-    piece_events.send(FallingPieceEvent::Spawn);
+    // Don't let any stale events apply to the next game.
+    piece_events.clear();
 }
 
-fn create_camera(config: &Config, commands: &mut Commands) -> Entity {
-    commands
-        .spawn(Camera3d::default())
-        .insert(
+/// Every player has their own camera looking at their own board. The UI is drawn by a separate camera on top so that
+/// menus cover the whole window.
+pub fn create_camera(config: Res<Config>, mut commands: Commands) {
+    for player in ALL_PLAYERS.iter().cloned() {
+        commands.spawn((
+            Camera3d::default(),
+            Camera {
+                order: player.index() as isize,
+                is_active: player == Player::One,
+                ..default()
+            },
             Transform::default()
-                .with_translation(config.camera_position)
-                .looking_at(config.camera_target, Vec3::Y),
-        )
-        .id()
+                .with_translation(player.board_offset() + config.camera_position)
+                .looking_at(player.board_offset() + config.camera_target, Vec3::Y),
+            player,
+        ));
+    }
+
+    commands.spawn((
+        Camera2d,
+        Camera {
+            order: ALL_PLAYERS.len() as isize,
+            clear_color: ClearColorConfig::None,
+            ..default()
+        },
+        IsDefaultUiCamera,
+    ));
+}
+
+/// Splits the window side by side between the players in the current game.
+pub fn split_screen(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    board_query: Query<&Player, With<Board>>,
+    mut camera_query: Query<(&mut Camera, &Player)>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let num_players = board_query.iter().count().max(1) as u32;
+    let window_size = window.physical_size();
+    let slice_width = window_size.x / num_players;
+
+    for (mut camera, player) in camera_query.iter_mut() {
+        let index = player.index() as u32;
+        let is_active = index < num_players;
+        let viewport = (num_players > 1 && is_active).then(|| Viewport {
+            physical_position: UVec2::new(index * slice_width, 0),
+            physical_size: UVec2::new(slice_width, window_size.y),
+            ..default()
+        });
+
+        if camera.is_active != is_active {
+            camera.is_active = is_active;
+        }
+        let viewport_rect =
+            |v: &Option<Viewport>| v.as_ref().map(|v| (v.physical_position, v.physical_size));
+        if viewport_rect(&camera.viewport) != viewport_rect(&viewport) {
+            camera.viewport = viewport;
+        }
+    }
 }
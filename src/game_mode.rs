@@ -0,0 +1,128 @@
+use crate::{Board, Config, GameOver, GameOverReason, GameStats, Player, ALL_PLAYERS};
+use bevy::prelude::*;
+
+/// The mode of the next or current game. High scores are kept separately for each mode.
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Resource, serde::Deserialize, serde::Serialize,
+)]
+pub enum GameMode {
+    /// Play until the stacks reach the top.
+    #[default]
+    Endless,
+    /// Clear a target number of lines, counted across both grids, as fast as possible.
+    Sprint,
+    /// Score as much as possible before the time runs out.
+    Ultra,
+    /// Score as much as possible while getting through a fixed number of levels.
+    Marathon,
+    /// Dig through garbage rows that keep rising from the bottom, clearing a target number of lines as fast as
+    /// possible.
+    Dig,
+    /// Two players side by side, sending garbage rows to each other. The last one standing wins.
+    Versus,
+    /// Solve a hand-made puzzle from the `PuzzleLibrary` with a fixed sequence of pieces.
+    Puzzle,
+}
+
+pub const ALL_GAME_MODES: [GameMode; 7] = [
+    GameMode::Endless,
+    GameMode::Sprint,
+    GameMode::Ultra,
+    GameMode::Marathon,
+    GameMode::Dig,
+    GameMode::Versus,
+    GameMode::Puzzle,
+];
+
+impl GameMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Endless => "Endless",
+            GameMode::Sprint => "Sprint",
+            GameMode::Ultra => "Ultra",
+            GameMode::Marathon => "Marathon",
+            GameMode::Dig => "Dig",
+            GameMode::Versus => "Versus",
+            GameMode::Puzzle => "Puzzle",
+        }
+    }
+
+    /// Case insensitive, for parsing command line arguments.
+    pub fn from_name(name: &str) -> Option<Self> {
+        ALL_GAME_MODES
+            .iter()
+            .cloned()
+            .find(|m| m.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The mode's goal from the `Config`: lines for Sprint and Dig, seconds for Ultra and levels for Marathon. Only
+    /// games with the same goal are comparable. Puzzles bring their own goals, see `check_puzzle_goal`.
+    pub fn goal(&self, config: &Config) -> Option<u32> {
+        match self {
+            GameMode::Endless | GameMode::Versus | GameMode::Puzzle => None,
+            GameMode::Sprint => Some(config.sprint_target_lines),
+            GameMode::Ultra => Some(config.ultra_time_limit_secs),
+            GameMode::Marathon => Some(config.marathon_levels),
+            GameMode::Dig => Some(config.dig_target_lines),
+        }
+    }
+
+    pub fn goal_reached(&self, config: &Config, stats: &GameStats) -> bool {
+        let Some(goal) = self.goal(config) else {
+            return false;
+        };
+
+        match self {
+            GameMode::Endless | GameMode::Versus | GameMode::Puzzle => false,
+            GameMode::Sprint | GameMode::Dig => stats.total_lines() >= goal,
+            GameMode::Ultra => stats.elapsed_secs() >= goal as f64,
+            GameMode::Marathon => stats.level() > goal,
+        }
+    }
+
+    /// Some modes rank games by the fastest completion instead of the highest score.
+    pub fn ranks_by_time(&self) -> bool {
+        match self {
+            GameMode::Sprint | GameMode::Dig => true,
+            GameMode::Endless
+            | GameMode::Ultra
+            | GameMode::Marathon
+            | GameMode::Versus
+            | GameMode::Puzzle => false,
+        }
+    }
+
+    /// Puzzles are only solved or not, and versus games are won or lost, so there's nothing to rank.
+    pub fn has_high_scores(&self) -> bool {
+        !matches!(self, GameMode::Versus | GameMode::Puzzle)
+    }
+
+    /// Endless is for practising and puzzles are for learning, so assists like placement hints are allowed there. Every
+    /// other mode is ranked, against a goal or another player.
+    pub fn allows_hints(&self) -> bool {
+        matches!(self, GameMode::Endless | GameMode::Puzzle)
+    }
+
+    pub fn players(&self) -> &'static [Player] {
+        match self {
+            GameMode::Versus => &ALL_PLAYERS,
+            _ => &[Player::One],
+        }
+    }
+}
+
+pub fn check_game_mode_goal(
+    mode: Res<GameMode>,
+    config: Res<Config>,
+    board_query: Query<(&Player, &GameStats), With<Board>>,
+    mut game_over: EventWriter<GameOver>,
+) {
+    for (player, stats) in board_query.iter() {
+        if mode.goal_reached(&config, stats) {
+            game_over.send(GameOver {
+                player: *player,
+                reason: GameOverReason::Completed,
+            });
+        }
+    }
+}
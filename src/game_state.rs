@@ -0,0 +1,85 @@
+use crate::{GameClock, GameOver, Player};
+use bevy::prelude::*;
+
+/// Gameplay systems only run in `Playing`. Grid and piece entities survive `Paused` and `GameOver`, and they're torn
+/// down when going back to `MainMenu` or restarting.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, States)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    Playing,
+    Paused,
+    GameOver,
+    HighScores,
+    Profiles,
+    PuzzleSelect,
+    /// Connected to the server and waiting for an online opponent.
+    Lobby,
+    /// Watching other people's games through the server. There's no way back to the menus.
+    Spectating,
+}
+
+/// Tears down the current game and starts a new one.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct RestartGame;
+
+pub fn toggle_pause(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keyboard.just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    match state.get() {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        _ => {}
+    }
+}
+
+/// Freezing virtual time keeps the drop timer and animations from advancing while paused.
+pub fn pause_time(
+    mut time: ResMut<Time<Virtual>>,
+    real_time: Res<Time<Real>>,
+    clock: Option<ResMut<GameClock>>,
+) {
+    time.pause();
+    if let Some(mut clock) = clock {
+        clock.pause(&real_time);
+    }
+}
+
+pub fn unpause_time(
+    mut time: ResMut<Time<Virtual>>,
+    real_time: Res<Time<Real>>,
+    clock: Option<ResMut<GameClock>>,
+) {
+    time.unpause();
+    if let Some(mut clock) = clock {
+        clock.unpause(&real_time);
+    }
+}
+
+/// The player whose board ended the game, e.g. the loser of a versus game.
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct GameOverPlayer(pub Player);
+
+/// The reason and player are kept as resources until the game is torn down.
+pub fn enter_game_over(
+    mut commands: Commands,
+    mut events: EventReader<GameOver>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if let Some(GameOver { player, reason }) = events.read().last() {
+        commands.insert_resource(*reason);
+        commands.insert_resource(GameOverPlayer(*player));
+        next_state.set(GameState::GameOver);
+    }
+}
+
+/// Should run after `teardown_game` when a `RestartGame` event is received.
+pub fn enter_playing(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Playing);
+}
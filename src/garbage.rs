@@ -0,0 +1,252 @@
+use crate::{
+    piece_fits, redraw_falling_piece, rotate_offset, Board, CellValue, Config, FallingPiece,
+    GameMode, GameOver, GameOverReason, GameSeed, Grid, GridSide, PieceMotion, PieceType, Player,
+    Rotation, ALL_PIECE_TYPES,
+};
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Garbage rows waiting to be pushed into the bottom of a player's grids. In dig mode, a new row is added every
+/// `dig_garbage_rise_secs`. In versus mode, rows are sent by the opponent.
+#[derive(Clone, Component, serde::Deserialize, serde::Serialize)]
+#[serde(from = "SavedGarbageRise", into = "SavedGarbageRise")]
+pub struct GarbageRise {
+    timer: Option<Timer>,
+    pending_rows: u32,
+    seed: u64,
+    rng: StdRng,
+    /// How many rows have been made, and how wide they were, which is how far along the RNG is.
+    rows_made: u64,
+    row_width: i32,
+}
+
+/// Like a saved `GrabBag`, the RNG is saved as how many rows it has made, and made to catch up when it's loaded.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SavedGarbageRise {
+    timer: Option<Timer>,
+    pending_rows: u32,
+    seed: u64,
+    rows_made: u64,
+    row_width: i32,
+}
+
+impl From<GarbageRise> for SavedGarbageRise {
+    fn from(garbage: GarbageRise) -> Self {
+        Self {
+            timer: garbage.timer,
+            pending_rows: garbage.pending_rows,
+            seed: garbage.seed,
+            rows_made: garbage.rows_made,
+            row_width: garbage.row_width,
+        }
+    }
+}
+
+impl From<SavedGarbageRise> for GarbageRise {
+    fn from(saved: SavedGarbageRise) -> Self {
+        let mut garbage = Self {
+            timer: saved.timer,
+            ..Self::new(None, saved.seed)
+        };
+        while garbage.rows_made < saved.rows_made {
+            garbage.next_rows(saved.row_width);
+        }
+        garbage.pending_rows = saved.pending_rows;
+
+        garbage
+    }
+}
+
+impl GarbageRise {
+    /// Without `secs_between_rows`, rows only arrive through `add_rows`.
+    pub fn new(secs_between_rows: Option<f32>, seed: u64) -> Self {
+        Self {
+            timer: secs_between_rows.map(|secs| Timer::from_seconds(secs, TimerMode::Repeating)),
+            pending_rows: 0,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            rows_made: 0,
+            row_width: 0,
+        }
+    }
+
+    pub fn pending_rows(&self) -> u32 {
+        self.pending_rows
+    }
+
+    pub fn add_rows(&mut self, rows: u32) {
+        self.pending_rows += rows;
+    }
+
+    /// Cancels up to `rows` pending rows and returns how many were left over.
+    pub fn cancel_rows(&mut self, rows: u32) -> u32 {
+        let cancelled = rows.min(self.pending_rows);
+        self.pending_rows -= cancelled;
+
+        rows - cancelled
+    }
+
+    /// Returns the cells of the next garbage row for each grid, left first.
+    ///
+    /// The holes are the footprint of the bottom layer of a random piece in a random orientation, projected onto each
+    /// grid. Filling both grids' holes with a single piece means dropping that piece in that orientation.
+    pub fn next_rows(&mut self, width: i32) -> [Vec<CellValue>; 2] {
+        self.rows_made += 1;
+        self.row_width = width;
+        let piece_type = ALL_PIECE_TYPES[self.rng.gen_range(0..ALL_PIECE_TYPES.len())];
+        let cells = self.random_orientation(piece_type);
+        let bottom = cells.iter().map(|p| p.y).min().unwrap();
+        let bottom_layer: Vec<IVec3> = cells.into_iter().filter(|p| p.y == bottom).collect();
+
+        let left_holes: Vec<i32> = bottom_layer.iter().map(|p| p.x).collect();
+        let right_holes: Vec<i32> = bottom_layer.iter().map(|p| p.z).collect();
+
+        [
+            self.garbage_row(width, &left_holes),
+            self.garbage_row(width, &right_holes),
+        ]
+    }
+
+    fn random_orientation(&mut self, piece_type: PieceType) -> Vec<IVec3> {
+        let mut cells: Vec<IVec3> = std::iter::once(IVec3::ZERO)
+            .chain(piece_type.cube_configuration().map(IVec3::from))
+            .collect();
+        // Pieces can only be rotated around X and Z, so only use orientations the player can reach.
+        for _ in 0..4 {
+            let rotation = match self.rng.gen_range(0..3) {
+                0 => Rotation::rotate_x_pos_90(),
+                1 => Rotation::rotate_z_pos_90(),
+                _ => continue,
+            };
+            for p in cells.iter_mut() {
+                *p = rotate_offset(rotation.matrix, *p);
+            }
+        }
+
+        cells
+    }
+
+    /// Shifts the holes to a random place in the row.
+    fn garbage_row(&mut self, width: i32, holes: &[i32]) -> Vec<CellValue> {
+        let min = *holes.iter().min().unwrap();
+        let max = *holes.iter().max().unwrap();
+        let shift = self.rng.gen_range(0..=width - 1 - (max - min)) - min;
+
+        (0..width)
+            .map(|x| {
+                if holes.contains(&(x - shift)) {
+                    CellValue::Empty
+                } else {
+                    CellValue::Garbage
+                }
+            })
+            .collect()
+    }
+}
+
+/// Pushes garbage rows into the bottom of both of a player's grids. Returns false if either stack was pushed out of the
+/// top.
+fn insert_garbage_rows(
+    player: Player,
+    garbage: &mut GarbageRise,
+    rows: u32,
+    grid_query: &mut Query<&mut Grid>,
+) -> bool {
+    let mut fits = true;
+    for _ in 0..rows {
+        let Some(width) = grid_query
+            .iter()
+            .find(|g| g.player() == player)
+            .map(|g| g.width())
+        else {
+            return true;
+        };
+        let [left, right] = garbage.next_rows(width);
+        for mut grid in grid_query.iter_mut().filter(|g| g.player() == player) {
+            let cells = match grid.side() {
+                GridSide::Left => &left,
+                GridSide::Right => &right,
+            };
+            fits &= grid.insert_row_from_bottom(cells);
+        }
+    }
+
+    fits
+}
+
+/// Should run after `create_game`.
+pub fn set_up_dig(
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    seed: Res<GameSeed>,
+    board_query: Query<(Entity, &Player), With<Board>>,
+    mut grid_query: Query<&mut Grid>,
+    mut commands: Commands,
+) {
+    if *mode != GameMode::Dig {
+        return;
+    }
+
+    for (board, player) in board_query.iter() {
+        let mut garbage = GarbageRise::new(Some(config.dig_garbage_rise_secs), seed.0);
+        insert_garbage_rows(
+            *player,
+            &mut garbage,
+            config.dig_initial_garbage_rows,
+            &mut grid_query,
+        );
+        commands.entity(board).insert(garbage);
+    }
+}
+
+/// New rows wait until the piece is falling in both grids and no rows are being cleared, so they never cut into a
+/// half-locked piece. The falling piece is pushed up along with the stacks if it would overlap them.
+pub fn raise_garbage(
+    time: Res<Time>,
+    mut board_query: Query<(&Player, &mut GarbageRise), With<Board>>,
+    mut grid_query: Query<&mut Grid>,
+    mut falling_piece_query: Query<(&mut FallingPiece, &mut PieceMotion)>,
+    mut game_over: EventWriter<GameOver>,
+) {
+    for (player, mut garbage) in board_query.iter_mut() {
+        let player = *player;
+        if let Some(timer) = &mut garbage.timer {
+            timer.tick(time.delta());
+            let rows = timer.times_finished_this_tick();
+            garbage.pending_rows += rows;
+        }
+
+        let grids_ready = grid_query
+            .iter()
+            .filter(|g| g.player() == player)
+            .all(|g| g.is_active() && !g.has_full_rows());
+        if garbage.pending_rows == 0 || !grids_ready {
+            continue;
+        }
+
+        let rows = std::mem::take(&mut garbage.pending_rows);
+        let mut topped_out = !insert_garbage_rows(player, &mut garbage, rows, &mut grid_query);
+
+        for (mut piece, mut motion) in falling_piece_query.iter_mut() {
+            if piece.player() != player {
+                continue;
+            }
+            for _ in 0..rows {
+                if piece_fits(&piece, &grid_query) {
+                    break;
+                }
+                piece.translate_n_rows(1);
+                motion.translate(Vec3::Y);
+            }
+            topped_out |= !piece_fits(&piece, &grid_query);
+            redraw_falling_piece(&piece, &mut grid_query);
+        }
+
+        if topped_out {
+            game_over.send(GameOver {
+                player,
+                reason: GameOverReason::ToppedOut,
+            });
+        }
+    }
+}
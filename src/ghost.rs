@@ -0,0 +1,96 @@
+use crate::{
+    cube_translation, move_accepted_in_grid, speculate_fast_drop_piece, FallingPiece, Grid,
+    PieceMaterials, PieceMotion,
+};
+use bevy::{color::palettes::css, prelude::*};
+
+/// A translucent copy of the falling piece, showing where the whole piece comes to rest if it's dropped. It's a child
+/// of the falling piece, so it's despawned with it.
+#[derive(Component)]
+pub struct GhostPiece;
+
+/// Laid out like the falling piece's own cubes, so it only needs to be moved down to where the piece comes to rest.
+pub fn spawn_ghost_piece(
+    piece: &FallingPiece,
+    materials: &PieceMaterials,
+    cube_mesh: Handle<Mesh>,
+    commands: &mut Commands,
+) -> Entity {
+    let cube_entities: Vec<Entity> = std::iter::once(IVec3::ZERO)
+        .chain(piece.offsets())
+        .map(|cube_offset| {
+            commands
+                .spawn((
+                    MeshMaterial3d(materials.get_ghost_material(piece.piece_type())),
+                    Mesh3d(cube_mesh.clone()),
+                    Transform::from_translation(cube_offset.as_vec3()),
+                ))
+                .id()
+        })
+        .collect();
+
+    commands
+        .spawn((GhostPiece, Transform::default(), Visibility::Hidden))
+        .add_children(&cube_entities)
+        .id()
+}
+
+/// Where the whole piece comes to rest if it's dropped: the first of the active grids to stop it locks it there, and
+/// from then on it only keeps falling in the others.
+pub fn speculate_first_lock_piece(piece: &FallingPiece, active_grids: &[&Grid]) -> FallingPiece {
+    let mut moved_piece = *piece;
+    loop {
+        let mut new_piece = moved_piece;
+        new_piece.translate_n_rows(-1);
+        if !active_grids
+            .iter()
+            .all(|g| move_accepted_in_grid(&new_piece, g))
+        {
+            return moved_piece;
+        }
+        moved_piece = new_piece;
+    }
+}
+
+/// Moves each ghost down to where its piece comes to rest, and outlines where the piece would land in each grid that
+/// lets it fall further, since it keeps falling there after the first grid locks it.
+pub fn update_ghost_pieces(
+    falling_piece_query: Query<(&FallingPiece, &PieceMotion, &Transform), Without<GhostPiece>>,
+    mut ghost_query: Query<(&Parent, &mut Transform, &mut Visibility), With<GhostPiece>>,
+    grid_query: Query<&Grid>,
+    mut gizmos: Gizmos,
+) {
+    for (parent, mut ghost_tfm, mut visibility) in ghost_query.iter_mut() {
+        let Ok((piece, motion, piece_tfm)) = falling_piece_query.get(parent.get()) else {
+            continue;
+        };
+        let active_grids: Vec<&Grid> = grid_query
+            .iter()
+            .filter(|g| g.player() == piece.player() && g.is_active())
+            .collect();
+
+        let first_lock = speculate_first_lock_piece(piece, &active_grids);
+        let rows = piece.center_position().y - first_lock.center_position().y;
+        // The ghost is a child of where the piece is drawn, which may still be catching up with where it really is.
+        let mut rest = *motion.target();
+        rest.translation -= Vec3::Y * rows as f32;
+        *ghost_tfm =
+            Transform::from_matrix(piece_tfm.compute_matrix().inverse() * rest.compute_matrix());
+        *visibility = if rows > 0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+
+        for grid in active_grids.iter() {
+            let landing = speculate_fast_drop_piece(piece, grid);
+            if landing.center_position().y == first_lock.center_position().y {
+                continue;
+            }
+            for p in landing.cell_positions() {
+                let translation = cube_translation(piece.player(), p);
+                gizmos.cuboid(Transform::from_translation(translation), css::WHITE);
+            }
+        }
+    }
+}
@@ -1,25 +1,106 @@
 use crate::{PieceType, ALL_PIECE_TYPES};
-use bevy::prelude::Resource;
-use rand::{prelude::SliceRandom, thread_rng};
+use bevy::prelude::Component;
+use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
 
-#[derive(Resource)]
+/// The same seed always produces the same sequence of pieces.
+#[derive(Clone, Component, serde::Deserialize, serde::Serialize)]
+#[serde(from = "SavedGrabBag", into = "SavedGrabBag")]
 pub struct GrabBag {
     repeats_per_bag: usize,
     bag: Vec<PieceType>,
+    seed: u64,
+    rng: StdRng,
+    /// How many times the bag has been shuffled, which is how far along the RNG is.
+    refills: u64,
+    fixed_sequence: Option<Vec<PieceType>>,
+}
+
+/// `StdRng` can't be serialized, so a saved bag keeps the number of refills instead, and the RNG is brought back to
+/// the same state by shuffling that many times.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SavedGrabBag {
+    repeats_per_bag: usize,
+    bag: Vec<PieceType>,
+    seed: u64,
+    refills: u64,
+    fixed_sequence: Option<Vec<PieceType>>,
+}
+
+impl From<GrabBag> for SavedGrabBag {
+    fn from(bag: GrabBag) -> Self {
+        Self {
+            repeats_per_bag: bag.repeats_per_bag,
+            bag: bag.bag,
+            seed: bag.seed,
+            refills: bag.refills,
+            fixed_sequence: bag.fixed_sequence,
+        }
+    }
+}
+
+impl From<SavedGrabBag> for GrabBag {
+    fn from(saved: SavedGrabBag) -> Self {
+        let mut bag = Self {
+            repeats_per_bag: saved.repeats_per_bag,
+            bag: Vec::new(),
+            seed: saved.seed,
+            rng: StdRng::seed_from_u64(saved.seed),
+            refills: 0,
+            fixed_sequence: saved.fixed_sequence,
+        };
+        while bag.refills < saved.refills {
+            bag.refill();
+        }
+        bag.bag = saved.bag;
+
+        bag
+    }
 }
 
 impl GrabBag {
-    pub fn new(repeats_per_bag: usize) -> Self {
+    pub fn new(repeats_per_bag: usize, seed: u64) -> Self {
         let mut bag = Self {
             repeats_per_bag,
             bag: Vec::new(),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            refills: 0,
+            fixed_sequence: None,
         };
         bag.refill();
 
         bag
     }
 
+    /// Always hands out `pieces` in order, starting over from the beginning when they run out.
+    pub fn from_sequence(pieces: Vec<PieceType>) -> Self {
+        assert!(!pieces.is_empty());
+
+        let mut bag = Self {
+            repeats_per_bag: 1,
+            bag: Vec::new(),
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
+            refills: 0,
+            fixed_sequence: Some(pieces),
+        };
+        bag.refill();
+
+        bag
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     fn refill(&mut self) {
+        self.refills += 1;
+        if let Some(sequence) = &self.fixed_sequence {
+            // Reversed because we pop from the back.
+            self.bag = sequence.iter().rev().cloned().collect();
+            return;
+        }
+
         let num_pieces = ALL_PIECE_TYPES.len();
         self.bag = ALL_PIECE_TYPES
             .iter()
@@ -27,7 +108,7 @@ impl GrabBag {
             .cycle()
             .take(num_pieces * self.repeats_per_bag)
             .collect();
-        self.bag.shuffle(&mut thread_rng());
+        self.bag.shuffle(&mut self.rng);
     }
 
     pub fn choose_next_piece_type(&mut self) -> PieceType {
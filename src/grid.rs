@@ -1,4 +1,4 @@
-use crate::{Config, FallingPiece, PieceMaterials, PieceType, SceneAssets};
+use crate::{Config, FallingPiece, PieceMaterials, PieceType, Player, SceneAssets};
 use bevy::prelude::*;
 
 // The `master` copy is never show to the player; it's only used for background
@@ -12,16 +12,29 @@ pub struct Grid {
     visible: Vec<CellValue>,
     entities: Vec<Entity>,
     projection: Box<dyn Projection>,
+    side: GridSide,
+    player: Player,
     active: bool,
+    unreported_full_rows: Vec<i32>,
 }
 
 pub trait Projection: Fn(IVec3) -> IVec2 + 'static + Send + Sync {}
 impl<T> Projection for T where T: Fn(IVec3) -> IVec2 + 'static + Send + Sync {}
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum GridSide {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub enum CellValue {
     Piece(PieceType),
+    /// Filled cells that didn't come from a piece, e.g. rows rising from the bottom in dig mode.
+    Garbage,
     DropHint,
+    /// Where the placement hint suggests putting the falling piece.
+    SuggestedHint,
     Empty,
 }
 
@@ -29,12 +42,56 @@ impl CellValue {
     pub fn is_piece(&self) -> bool {
         matches!(self, CellValue::Piece(_))
     }
+
+    /// Whether a falling piece collides with this cell.
+    pub fn is_filled(&self) -> bool {
+        matches!(self, CellValue::Piece(_) | CellValue::Garbage)
+    }
 }
 
 #[derive(Component)]
 pub struct GridCell;
 
 impl Grid {
+    /// A grid without any cell entities, for simulations that never draw it, like `Environment`.
+    pub fn headless(side: GridSide, player: Player, grid_size: [usize; 2]) -> Self {
+        Self::new(side, player, grid_size, side_projection(side), Vec::new())
+    }
+
+    fn new(
+        side: GridSide,
+        player: Player,
+        grid_size: [usize; 2],
+        projection: Box<dyn Projection>,
+        entities: Vec<Entity>,
+    ) -> Self {
+        let extent = Extent::new(
+            IVec2::ZERO,
+            IVec2::new(grid_size[0] as i32, grid_size[1] as i32),
+        );
+        let n_cells = extent.size();
+
+        Self {
+            extent,
+            master: vec![CellValue::Empty; n_cells],
+            visible: vec![CellValue::Empty; n_cells],
+            entities,
+            projection,
+            side,
+            player,
+            active: true,
+            unreported_full_rows: Vec::new(),
+        }
+    }
+
+    pub fn side(&self) -> GridSide {
+        self.side
+    }
+
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
     pub fn width(&self) -> i32 {
         self.extent.shape.x
     }
@@ -59,6 +116,55 @@ impl Grid {
         &self.master
     }
 
+    /// Replaces the whole grid, e.g. to set up a puzzle.
+    pub fn fill_master(&mut self, value_at: impl Fn(IVec2) -> CellValue) {
+        let shape = self.extent.shape;
+        for p in self.extent.iter_points() {
+            self.master[index2(shape, p)] = value_at(p);
+        }
+        self.copy_master_to_visible();
+    }
+
+    /// Whether a falling piece would collide with the cell, ignoring the falling piece itself.
+    pub fn is_filled(&self, p: IVec2) -> bool {
+        self.master[index2(self.extent.shape, p)].is_filled()
+    }
+
+    /// How many rows there are up to and including the highest filled cell.
+    pub fn stack_height(&self) -> i32 {
+        (0..self.height())
+            .rev()
+            .find(|row| self.row_is_occupied(*row))
+            .map_or(0, |row| row + 1)
+    }
+
+    /// The cells as the player sees them, row by row from the bottom.
+    pub fn visible_cells(&self) -> &[CellValue] {
+        &self.visible
+    }
+
+    /// Replaces the whole grid with cells from `visible_cells`, e.g. from another player's machine. Cells for a grid
+    /// of a different size are ignored.
+    pub fn set_visible_cells(&mut self, cells: &[CellValue]) {
+        if cells.len() != self.visible.len() {
+            return;
+        }
+        self.master.copy_from_slice(cells);
+        self.copy_master_to_visible();
+    }
+
+    /// The locked cells, without the falling piece or any hints, e.g. to save the game.
+    pub fn master_cells(&self) -> &[CellValue] {
+        &self.master
+    }
+
+    /// Puts back a grid from its `master_cells` and whether it was active, e.g. to resume a saved game. Cells for a
+    /// grid of a different size are ignored.
+    pub fn restore(&mut self, cells: &[CellValue], active: bool) {
+        self.set_visible_cells(cells);
+        self.active = active;
+    }
+
     pub fn copy_master_to_visible(&mut self) {
         self.visible.copy_from_slice(&self.master);
     }
@@ -67,12 +173,37 @@ impl Grid {
         self.master.copy_from_slice(&self.visible);
     }
 
+    // Full rows are left in place so they can be animated. They only get removed when `collapse_full_rows` is called.
     fn commit(&mut self) {
         self.copy_visible_to_master();
+        self.unreported_full_rows = self.full_rows();
+        self.copy_master_to_visible();
+    }
+
+    fn full_rows(&self) -> Vec<i32> {
+        (0..self.height())
+            .filter(|row| self.row_is_full(*row))
+            .collect()
+    }
+
+    pub fn has_full_rows(&self) -> bool {
+        (0..self.height()).any(|row| self.row_is_full(row))
+    }
+
+    /// Returns the rows that became full on the last commit, unless they were already taken.
+    pub fn take_unreported_full_rows(&mut self) -> Vec<i32> {
+        std::mem::take(&mut self.unreported_full_rows)
+    }
+
+    pub fn collapse_full_rows(&mut self) {
         self.eliminate_full_rows();
         self.copy_master_to_visible();
     }
 
+    pub fn cell_entity(&self, p: IVec2) -> Entity {
+        self.entities[index2(self.extent.shape, p)]
+    }
+
     fn eliminate_full_rows(&mut self) {
         let mut rows_to_check = self.height();
         let mut check_row = 0;
@@ -99,6 +230,52 @@ impl Grid {
         }
     }
 
+    /// Pushes every row up by one and puts `cells` in the bottom row. Returns false if anything was pushed out of the
+    /// top of the grid.
+    pub fn insert_row_from_bottom(&mut self, cells: &[CellValue]) -> bool {
+        assert_eq!(cells.len(), self.width() as usize);
+
+        let top_row = self.height() - 1;
+        let overflowed = self.row_is_occupied(top_row);
+        self.shift_rows_up(0, top_row);
+
+        let shape = self.extent.shape;
+        let row = self.row_extent(0);
+        let master_cells = self.edit_master();
+        for (p, value) in row.iter_points().zip(cells.iter().cloned()) {
+            master_cells[index2(shape, p)] = value;
+        }
+        self.copy_master_to_visible();
+
+        !overflowed
+    }
+
+    // The reverse of `shift_rows_down`. Rows are shifted from the top so they don't overwrite each other, and the top
+    // row is dropped.
+    fn shift_rows_up(&mut self, start_row: i32, end_row: i32) {
+        for row in (start_row..end_row).rev() {
+            self.shift_row_up(row);
+        }
+    }
+
+    fn shift_row_up(&mut self, row: i32) {
+        let shape = self.extent.shape;
+        let row = self.row_extent(row);
+        let master_cells = self.edit_master();
+        for p in row.iter_points() {
+            let p_val = master_cells[index2(shape, p)];
+            master_cells[index2(shape, p + IVec2::new(0, 1))] = p_val;
+        }
+    }
+
+    fn row_is_occupied(&self, row: i32) -> bool {
+        let shape = self.extent.shape;
+        let row = self.row_extent(row);
+        let master_cells = self.read_master_channel();
+        row.iter_points()
+            .any(|p| master_cells[index2(shape, p)].is_filled())
+    }
+
     fn shift_row_down(&mut self, row: i32) {
         let shape = self.extent.shape;
         let row = self.row_extent(row);
@@ -136,7 +313,7 @@ impl Grid {
         check_cells
             .iter()
             .cloned()
-            .any(|p| master_cells[index2(shape, p)].is_piece())
+            .any(|p| master_cells[index2(shape, p)].is_filled())
     }
 
     fn any_cells_out_of_bounds(&self, check_cells: &[IVec2]) -> bool {
@@ -173,6 +350,19 @@ impl Grid {
         }
     }
 
+    /// Marks cells in the visible copy without covering the falling piece or anything locked, e.g. for hints that
+    /// should still show the piece when it's in the right place.
+    pub fn write_hint_cells(&mut self, cells: &[IVec2], value: CellValue) {
+        let shape = self.extent.shape;
+        let visible_cells = self.edit_visible();
+        for cell_p in cells.iter().cloned() {
+            let cell = &mut visible_cells[index2(shape, cell_p)];
+            if !cell.is_filled() {
+                *cell = value;
+            }
+        }
+    }
+
     pub fn deactivate(&mut self) {
         self.commit();
         self.active = false;
@@ -186,12 +376,9 @@ impl Grid {
         self.active
     }
 
-    fn project_piece(&self, piece: &FallingPiece) -> Vec<IVec2> {
-        piece
-            .cell_positions()
-            .iter()
-            .map(|p| (self.projection)(*p))
-            .collect()
+    /// Where the piece's cubes end up in this grid.
+    pub fn project_piece(&self, piece: &FallingPiece) -> [IVec2; 4] {
+        piece.cell_positions().map(|p| (self.projection)(p))
     }
 
     fn sync_cell_materials(
@@ -216,39 +403,54 @@ pub enum PieceCollisionResult {
     NoCollision,
 }
 
-pub fn create_grids(config: &Config, scene_assets: &SceneAssets, commands: &mut Commands) {
-    let grid_size = config.grid_size;
+/// Where a grid is relative to its board. All cells are locally in the XY plane, so each grid is rotated to face the
+/// plane it projects onto, either XY or ZY, then tilted a little towards the camera.
+pub fn grid_transform(side: GridSide, config: &Config) -> Transform {
+    match side {
+        GridSide::Left => Transform {
+            translation: config.grid_offset * -Vec3::Z,
+            rotation: Quat::from_axis_angle(Vec3::Y, config.grid_tilt_angle),
+            scale: Vec3::ONE,
+        },
+        GridSide::Right => Transform {
+            translation: config.grid_offset * -Vec3::X,
+            rotation: Quat::from_axis_angle(
+                Vec3::Y,
+                -(std::f32::consts::FRAC_PI_2 + config.grid_tilt_angle),
+            ),
+            scale: Vec3::ONE,
+        },
+    }
+}
 
-    // All cells are locally in the XY plane, so we rotate the parent entity for each grid to fall into the correct plane,
-    // either XY or ZY.
+/// `grid_size` usually comes from `config`, but a spectator uses the size of the game it's watching.
+pub fn create_grids(
+    player: Player,
+    grid_size: [usize; 2],
+    config: &Config,
+    scene_assets: &SceneAssets,
+    commands: &mut Commands,
+) {
+    let board_offset = player.board_offset();
 
-    let left_grid_projection = Box::new(|p: IVec3| p.xy());
-    let left_grid_transform = Transform {
-        translation: config.grid_offset * -Vec3::Z,
-        rotation: Quat::from_axis_angle(Vec3::Y, config.grid_tilt_angle),
-        scale: Vec3::ONE,
-    };
+    let mut left_grid_transform = grid_transform(GridSide::Left, config);
+    left_grid_transform.translation += board_offset;
     spawn_grid(
+        GridSide::Left,
+        player,
         grid_size,
-        left_grid_projection,
         left_grid_transform,
         commands,
         &scene_assets.piece_materials,
         scene_assets.left_cell_mesh.clone(),
     );
 
-    let right_grid_projection = Box::new(|p: IVec3| p.zy());
-    let right_grid_transform = Transform {
-        translation: config.grid_offset * -Vec3::X,
-        rotation: Quat::from_axis_angle(
-            Vec3::Y,
-            -(std::f32::consts::FRAC_PI_2 + config.grid_tilt_angle),
-        ),
-        scale: Vec3::ONE,
-    };
+    let mut right_grid_transform = grid_transform(GridSide::Right, config);
+    right_grid_transform.translation += board_offset;
     spawn_grid(
+        GridSide::Right,
+        player,
         grid_size,
-        right_grid_projection,
         right_grid_transform,
         commands,
         &scene_assets.piece_materials,
@@ -256,9 +458,18 @@ pub fn create_grids(config: &Config, scene_assets: &SceneAssets, commands: &mut
     );
 }
 
+/// The left grid shows the XY plane and the right grid shows the ZY plane.
+fn side_projection(side: GridSide) -> Box<dyn Projection> {
+    match side {
+        GridSide::Left => Box::new(|p: IVec3| p.xy()),
+        GridSide::Right => Box::new(|p: IVec3| p.zy()),
+    }
+}
+
 fn spawn_grid(
+    side: GridSide,
+    player: Player,
     grid_size: [usize; 2],
-    projection: Box<dyn Projection>,
     grid_transform: Transform,
     commands: &mut Commands,
     piece_materials: &PieceMaterials,
@@ -266,22 +477,18 @@ fn spawn_grid(
 ) -> Entity {
     let shape = IVec2::new(grid_size[0] as i32, grid_size[1] as i32);
     let extent = Extent::new(IVec2::ZERO, shape);
-    let n_cells = extent.size();
-    let master = vec![CellValue::Empty; n_cells];
-    let visible = vec![CellValue::Empty; n_cells];
     let entities = spawn_cells(extent, commands, piece_materials, cell_mesh);
 
     commands
         .spawn_empty()
         .add_children(&entities)
-        .insert(Grid {
-            extent,
+        .insert(Grid::new(
+            side,
+            player,
+            grid_size,
+            side_projection(side),
             entities,
-            master,
-            visible,
-            projection,
-            active: true,
-        })
+        ))
         .insert(GlobalTransform::default())
         .insert(grid_transform)
         .id()
@@ -302,14 +509,18 @@ fn spawn_cells(
             .spawn(GridCell)
             .insert(Mesh3d(cell_mesh.clone()))
             .insert(MeshMaterial3d(materials.empty_cell_material()))
-            // We have to offset by 0.5 because the cell meshes are centered at (0, 0).
-            .insert(Transform::from_xyz(p.x as f32 + 0.5, p.y as f32 + 0.5, 0.0))
+            .insert(Transform::from_translation(cell_translation(p)))
             .id();
         entities[i] = entity;
     }
     entities
 }
 
+pub fn cell_translation(p: IVec2) -> Vec3 {
+    // We have to offset by 0.5 because the cell meshes are centered at (0, 0).
+    Vec3::new(p.x as f32 + 0.5, p.y as f32 + 0.5, 0.0)
+}
+
 pub fn synchronize_grid_materials(
     grids_query: Query<&Grid>,
     assets: Res<SceneAssets>,
@@ -341,7 +552,7 @@ impl Extent {
 
     fn contains(&self, p: IVec2) -> bool {
         let max = self.max();
-        self.min.x < p.x && p.x <= max.x && self.min.y < p.y && p.y <= max.y
+        self.min.x <= p.x && p.x <= max.x && self.min.y <= p.y && p.y <= max.y
     }
 
     fn iter_points(&self) -> impl Iterator<Item = IVec2> {
@@ -0,0 +1,35 @@
+use crate::{cell_translation, cube_translation, Config, FallingPiece, Grid, PieceMotion};
+use bevy::prelude::*;
+
+/// Draws a faint ray from each cube of the falling piece to the cell it lights up in each grid it's still falling in,
+/// to show how the piece casts its shadows. Turned on with `Config::projection_guides`.
+pub fn draw_projection_guides(
+    config: Res<Config>,
+    falling_piece_query: Query<(&FallingPiece, &PieceMotion, &Transform)>,
+    grid_query: Query<(&Grid, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    if !config.projection_guides {
+        return;
+    }
+    let color = Color::WHITE.with_alpha(config.projection_guide_opacity);
+
+    for (piece, motion, tfm) in falling_piece_query.iter() {
+        // The rays start from the cubes where they're drawn, even while they're still sliding into place.
+        let to_drawn = tfm.compute_affine() * motion.target().compute_affine().inverse();
+        for (grid, grid_tfm) in grid_query.iter() {
+            if grid.player() != piece.player() || !grid.is_active() {
+                continue;
+            }
+            let cells = grid.project_piece(piece);
+            for (p, cell) in piece.cell_positions().iter().zip(cells.iter()) {
+                let cube = cube_translation(piece.player(), *p);
+                gizmos.line(
+                    to_drawn.transform_point3(cube),
+                    grid_tfm.transform_point(cell_translation(*cell)),
+                    color,
+                );
+            }
+        }
+    }
+}
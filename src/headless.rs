@@ -0,0 +1,48 @@
+use crate::{Board, GameStats, Player, ReplayPlayback};
+use bevy::prelude::*;
+
+/// How long a finished replay waits for its last moves to play out before the results are reported.
+const REPLAY_SETTLE_SECS: f32 = 1.0;
+
+/// The game is running without a window, from `--headless`, and exits once there are results to report.
+#[derive(Resource)]
+pub struct Headless;
+
+/// Prints each player's results and exits. Runs when the game ends.
+pub fn report_headless_results(
+    board_query: Query<(&Player, &GameStats), With<Board>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let mut boards: Vec<_> = board_query.iter().collect();
+    boards.sort_by_key(|(player, _)| **player);
+    for (player, stats) in boards {
+        println!(
+            "{:?}: score {}, lines {}, pieces {}, {:.1}s",
+            player,
+            stats.score(),
+            stats.total_lines(),
+            stats.pieces_placed(),
+            stats.elapsed_secs()
+        );
+    }
+
+    exit.send(AppExit::Success);
+}
+
+/// A replay of a game that was quit before it ended never reaches the game over screen, so its results are reported
+/// once every move has been played.
+pub fn finish_headless_replay(
+    time: Res<Time>,
+    playback: Res<ReplayPlayback>,
+    mut settle: Local<f32>,
+    board_query: Query<(&Player, &GameStats), With<Board>>,
+    exit: EventWriter<AppExit>,
+) {
+    if !playback.is_finished() {
+        return;
+    }
+    *settle += time.delta_secs();
+    if *settle >= REPLAY_SETTLE_SECS {
+        report_headless_results(board_query, exit);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{
+    cube_translation, redraw_falling_piece, Agent, Autoplay, Board, CellValue, Config,
+    FallingPiece, GameMode, Grid, GridSide, HeuristicAgent, PieceState, Player, Remote,
+};
+use bevy::{color::palettes::css, prelude::*};
+
+/// Whether suggested placements are shown, toggled with `Config::toggle_hints_key`. Stays the same from game to game.
+#[derive(Default, Resource)]
+pub struct HintsEnabled(pub bool);
+
+/// Where a `HeuristicAgent` would put the board's falling piece, shown to help people learn how orientations map to
+/// shadows. It's worked out once per piece, from where the piece spawned.
+#[derive(Component, Default)]
+pub struct PlacementHint {
+    /// The piece that `locked` was worked out for.
+    piece: Option<Entity>,
+    /// Where the piece would lock in each grid, left then right.
+    locked: [Option<FallingPiece>; 2],
+    assisted: bool,
+}
+
+impl PlacementHint {
+    /// Whether a hint has been shown at any point in the game, which keeps it out of the high scores.
+    pub fn assisted(&self) -> bool {
+        self.assisted
+    }
+}
+
+/// Should run after `create_game`. Boards played by the bot or by someone else don't get hints.
+pub fn add_placement_hints(
+    mode: Res<GameMode>,
+    board_query: Query<Entity, (With<Board>, Without<Remote>)>,
+    mut commands: Commands,
+) {
+    if !mode.allows_hints() {
+        return;
+    }
+    for board in board_query.iter() {
+        commands.entity(board).insert(PlacementHint::default());
+    }
+}
+
+pub fn toggle_hints(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    mut enabled: ResMut<HintsEnabled>,
+    falling_piece_query: Query<&FallingPiece>,
+    mut grid_query: Query<&mut Grid>,
+) {
+    if !keyboard.just_pressed(config.toggle_hints_key) || !mode.allows_hints() {
+        return;
+    }
+    enabled.0 = !enabled.0;
+
+    // Hint cells are only drawn over, so they need to be wiped when hints are turned off.
+    if !enabled.0 {
+        for piece in falling_piece_query.iter() {
+            redraw_falling_piece(piece, &mut grid_query);
+        }
+    }
+}
+
+/// Works out a hint whenever a new piece spawns, then marks it in the active grids with `CellValue::SuggestedHint` and
+/// draws it as a wireframe. Should run after anything that redraws the grids.
+#[allow(clippy::type_complexity)]
+pub fn show_placement_hints(
+    enabled: Res<HintsEnabled>,
+    mut board_query: Query<(&Player, &mut PlacementHint), (With<Board>, Without<Autoplay>)>,
+    falling_piece_query: Query<(Entity, &FallingPiece)>,
+    mut grid_query: Query<&mut Grid>,
+    mut gizmos: Gizmos,
+) {
+    if !enabled.0 {
+        return;
+    }
+
+    for (player, mut hint) in board_query.iter_mut() {
+        let Some((piece_entity, piece)) = falling_piece_query
+            .iter()
+            .find(|(_, piece)| piece.player() == *player)
+        else {
+            continue;
+        };
+        hint.assisted = true;
+
+        if hint.piece != Some(piece_entity) {
+            let grid = |side| {
+                grid_query
+                    .iter()
+                    .find(|g| g.player() == *player && g.side() == side)
+            };
+            let (Some(left), Some(right)) = (grid(GridSide::Left), grid(GridSide::Right)) else {
+                continue;
+            };
+            hint.piece = Some(piece_entity);
+            hint.locked = match HeuristicAgent::default().choose_placement(left, right, piece) {
+                Some(placement) => {
+                    let mut state = PieceState::new(*piece);
+                    for action in placement.path.iter() {
+                        state.apply(action, [left, right]);
+                    }
+                    state.locked
+                }
+                None => [None; 2],
+            };
+        }
+
+        for mut grid in grid_query.iter_mut().filter(|g| g.player() == *player) {
+            let index = match grid.side() {
+                GridSide::Left => 0,
+                GridSide::Right => 1,
+            };
+            if let (true, Some(locked)) = (grid.is_active(), hint.locked[index]) {
+                let cells = grid.project_piece(&locked);
+                grid.write_hint_cells(&cells, CellValue::SuggestedHint);
+            }
+        }
+
+        // The piece usually locks in the same place in both grids, but not always.
+        let mut ghosts: Vec<FallingPiece> = Vec::new();
+        for locked in hint.locked.iter().flatten() {
+            if !ghosts
+                .iter()
+                .any(|g| g.cell_positions() == locked.cell_positions())
+            {
+                ghosts.push(*locked);
+            }
+        }
+        for ghost in ghosts.iter() {
+            for p in ghost.cell_positions() {
+                let translation = cube_translation(*player, p);
+                gizmos.cuboid(Transform::from_translation(translation), css::AQUA);
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use crate::{
+    ActivePuzzle, Board, Config, GameMode, GameStats, GarbageRise, Grid, GridSide, PersonalBest,
+    Player, Remote,
+};
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub struct Hud;
+
+#[derive(Clone, Copy, Component, Debug)]
+pub enum HudField {
+    Score,
+    Level,
+    Lines(GridSide),
+    TotalLines,
+    PiecesPlaced,
+    Time,
+    PiecesPerSecond,
+    /// Lines cleared out of the mode's target.
+    LineProgress,
+    /// The current level out of the mode's last level.
+    LevelProgress,
+    TimeRemaining,
+    /// The latest split, compared to the personal best.
+    LastSplit,
+    PersonalBest,
+    PuzzleName,
+    /// Rows cleared in one grid out of the puzzle's goal.
+    PuzzleRows(GridSide),
+    /// Pieces placed out of the puzzle's limit.
+    PuzzlePieces,
+    /// Garbage rows on their way from the opponent.
+    IncomingGarbage,
+}
+
+/// What a `HudField` might need to show, besides the player's `GameStats`.
+pub struct HudContext<'a> {
+    pub goal: Option<u32>,
+    pub personal_best: Option<&'a PersonalBest>,
+    pub puzzle: Option<&'a ActivePuzzle>,
+    pub incoming_garbage: u32,
+}
+
+impl HudField {
+    pub fn fields_for_mode(mode: GameMode) -> &'static [HudField] {
+        match mode {
+            GameMode::Endless => &[
+                HudField::Score,
+                HudField::Level,
+                HudField::Lines(GridSide::Left),
+                HudField::Lines(GridSide::Right),
+                HudField::TotalLines,
+                HudField::PiecesPlaced,
+                HudField::Time,
+                HudField::PiecesPerSecond,
+            ],
+            GameMode::Sprint => &[
+                HudField::Time,
+                HudField::LineProgress,
+                HudField::Lines(GridSide::Left),
+                HudField::Lines(GridSide::Right),
+                HudField::LastSplit,
+                HudField::PersonalBest,
+                HudField::PiecesPlaced,
+                HudField::PiecesPerSecond,
+            ],
+            GameMode::Ultra => &[
+                HudField::TimeRemaining,
+                HudField::Score,
+                HudField::Level,
+                HudField::Lines(GridSide::Left),
+                HudField::Lines(GridSide::Right),
+                HudField::TotalLines,
+                HudField::PiecesPlaced,
+                HudField::PiecesPerSecond,
+            ],
+            GameMode::Marathon => &[
+                HudField::Score,
+                HudField::LevelProgress,
+                HudField::Lines(GridSide::Left),
+                HudField::Lines(GridSide::Right),
+                HudField::TotalLines,
+                HudField::PiecesPlaced,
+                HudField::Time,
+                HudField::PiecesPerSecond,
+            ],
+            GameMode::Dig => &[
+                HudField::Time,
+                HudField::LineProgress,
+                HudField::Lines(GridSide::Left),
+                HudField::Lines(GridSide::Right),
+                HudField::PersonalBest,
+                HudField::PiecesPlaced,
+                HudField::PiecesPerSecond,
+            ],
+            GameMode::Versus => &[
+                HudField::Score,
+                HudField::IncomingGarbage,
+                HudField::Lines(GridSide::Left),
+                HudField::Lines(GridSide::Right),
+                HudField::PiecesPlaced,
+                HudField::PiecesPerSecond,
+            ],
+            GameMode::Puzzle => &[
+                HudField::PuzzleName,
+                HudField::PuzzleRows(GridSide::Left),
+                HudField::PuzzleRows(GridSide::Right),
+                HudField::PuzzlePieces,
+                HudField::Time,
+            ],
+        }
+    }
+
+    fn text(&self, stats: &GameStats, context: &HudContext) -> String {
+        let HudContext {
+            goal,
+            personal_best: pb,
+            puzzle,
+            incoming_garbage,
+        } = *context;
+        match self {
+            HudField::Score => format!("Score: {}", stats.score()),
+            HudField::Level => format!("Level: {}", stats.level()),
+            HudField::Lines(GridSide::Left) => {
+                format!("Left lines: {}", stats.lines(GridSide::Left))
+            }
+            HudField::Lines(GridSide::Right) => {
+                format!("Right lines: {}", stats.lines(GridSide::Right))
+            }
+            HudField::TotalLines => format!("Total lines: {}", stats.total_lines()),
+            HudField::PiecesPlaced => format!("Pieces: {}", stats.pieces_placed()),
+            HudField::Time => format!("Time: {}", format_time(stats.elapsed_secs())),
+            HudField::PiecesPerSecond => format!("PPS: {:.2}", stats.pieces_per_second()),
+            HudField::LineProgress => match goal {
+                Some(target) => format!("Lines: {}/{}", stats.total_lines(), target),
+                None => format!("Lines: {}", stats.total_lines()),
+            },
+            HudField::LevelProgress => match goal {
+                Some(last_level) => format!("Level: {}/{}", stats.level(), last_level),
+                None => format!("Level: {}", stats.level()),
+            },
+            HudField::TimeRemaining => {
+                let remaining = goal.map_or(0.0, |limit| limit as f64 - stats.elapsed_secs());
+                format!("Time left: {}", format_time(remaining.max(0.0)))
+            }
+            HudField::LastSplit => {
+                let splits = stats.line_splits();
+                let Some(split) = splits.last() else {
+                    return "Split: -".into();
+                };
+                let i = splits.len() - 1;
+                let pb_split = pb.and_then(|pb| pb.0.line_splits.get(i));
+                match pb_split {
+                    Some(pb_split) => format!(
+                        "Split {}: {} ({:+.3})",
+                        splits.len(),
+                        format_time(*split),
+                        split - pb_split
+                    ),
+                    None => format!("Split {}: {}", splits.len(), format_time(*split)),
+                }
+            }
+            HudField::PersonalBest => match pb {
+                Some(pb) => format!("PB: {}", format_time(pb.0.duration_secs)),
+                None => "PB: -".into(),
+            },
+            HudField::PuzzleName => match puzzle {
+                Some(puzzle) => format!("Puzzle: {}", puzzle.0.name),
+                None => "Puzzle: -".into(),
+            },
+            HudField::PuzzleRows(side) => {
+                let label = match side {
+                    GridSide::Left => "Left",
+                    GridSide::Right => "Right",
+                };
+                match puzzle {
+                    Some(puzzle) => format!(
+                        "{}: {}/{}",
+                        label,
+                        stats.lines(*side),
+                        puzzle.0.goal.rows_per_grid
+                    ),
+                    None => format!("{}: {}", label, stats.lines(*side)),
+                }
+            }
+            HudField::PuzzlePieces => match puzzle {
+                Some(puzzle) => format!(
+                    "Pieces: {}/{}",
+                    stats.pieces_placed(),
+                    puzzle.0.goal.max_pieces
+                ),
+                None => format!("Pieces: {}", stats.pieces_placed()),
+            },
+            HudField::IncomingGarbage => {
+                format!("Incoming: {}", incoming_garbage)
+            }
+        }
+    }
+}
+
+/// Formats with millisecond precision, like "1:02.345".
+pub fn format_time(secs: f64) -> String {
+    let minutes = (secs / 60.0) as u32;
+    let seconds = secs % 60.0;
+
+    format!("{}:{:06.3}", minutes, seconds)
+}
+
+const HUD_MARGIN: f32 = 10.0;
+
+pub fn spawn_hud(
+    mode: Res<GameMode>,
+    board_query: Query<&Player, With<Board>>,
+    mut commands: Commands,
+) {
+    for player in board_query.iter().cloned() {
+        commands
+            .spawn((
+                Hud,
+                player,
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(HUD_MARGIN),
+                    top: Val::Px(HUD_MARGIN),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(8.0)),
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                },
+                BackgroundColor(Color::BLACK.with_alpha(0.4)),
+            ))
+            .with_children(|parent| {
+                if mode.players().len() > 1 {
+                    parent.spawn(Text::new(player.name()));
+                }
+                for field in HudField::fields_for_mode(*mode).iter().cloned() {
+                    parent.spawn((
+                        field,
+                        player,
+                        Text::default(),
+                        TextFont {
+                            font_size: 18.0,
+                            ..default()
+                        },
+                    ));
+                }
+            });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_hud(
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    board_query: Query<(&Player, &GameStats, Option<&GarbageRise>, Option<&Remote>), With<Board>>,
+    personal_best: Option<Res<PersonalBest>>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    mut text_query: Query<(&HudField, &Player, &mut Text)>,
+) {
+    for (board_player, stats, garbage, remote) in board_query.iter() {
+        let incoming_garbage = match (garbage, remote) {
+            (Some(garbage), _) => garbage.pending_rows(),
+            (None, Some(remote)) => remote.incoming_garbage(),
+            (None, None) => 0,
+        };
+        let context = HudContext {
+            goal: mode.goal(&config),
+            personal_best: personal_best.as_deref(),
+            puzzle: puzzle.as_deref(),
+            incoming_garbage,
+        };
+        for (field, _, mut text) in text_query
+            .iter_mut()
+            .filter(|(_, player, _)| *player == board_player)
+        {
+            text.0 = field.text(stats, &context);
+        }
+    }
+}
+
+/// The grids are tilted towards the camera, so where they land on screen depends on `grid_tilt_angle`, `grid_offset`
+/// and the camera. We project the grid corners to the screen and put the HUD on whichever side has more free space.
+pub fn layout_hud(
+    camera_query: Query<(&Camera, &GlobalTransform, &Player)>,
+    grid_query: Query<(&Grid, &GlobalTransform)>,
+    mut hud_query: Query<(&mut Node, &ComputedNode, &Player), With<Hud>>,
+) {
+    for (mut node, computed, player) in hud_query.iter_mut() {
+        let Some((camera, camera_tfm, _)) = camera_query.iter().find(|(_, _, p)| *p == player)
+        else {
+            continue;
+        };
+        // With split screen, the player's view only covers part of the window.
+        let Some(viewport) = camera.logical_viewport_rect() else {
+            continue;
+        };
+
+        let mut grids_min_x = f32::INFINITY;
+        let mut grids_max_x = f32::NEG_INFINITY;
+        for (grid, grid_tfm) in grid_query.iter().filter(|(g, _)| g.player() == *player) {
+            let (w, h) = (grid.width() as f32, grid.height() as f32);
+            for corner in [
+                Vec3::ZERO,
+                Vec3::new(w, 0.0, 0.0),
+                Vec3::new(0.0, h, 0.0),
+                Vec3::new(w, h, 0.0),
+            ] {
+                let world = grid_tfm.transform_point(corner);
+                if let Ok(screen) = camera.world_to_viewport(camera_tfm, world) {
+                    grids_min_x = grids_min_x.min(screen.x);
+                    grids_max_x = grids_max_x.max(screen.x);
+                }
+            }
+        }
+        if !grids_min_x.is_finite() {
+            continue;
+        }
+
+        let hud_width = computed.size().x * computed.inverse_scale_factor();
+        let space_left = grids_min_x;
+        let space_right = viewport.width() - grids_max_x;
+
+        let x = if space_left >= hud_width + 2.0 * HUD_MARGIN || space_left >= space_right {
+            viewport.min.x + HUD_MARGIN
+        } else {
+            viewport.max.x - hud_width - HUD_MARGIN
+        };
+        let left = Val::Px(x);
+        if node.left != left {
+            node.left = left;
+        }
+    }
+}
@@ -1,21 +1,83 @@
+mod agent;
 mod assets;
+mod audio;
+mod autoplay;
+mod cli;
 mod config;
+mod config_reload;
 mod controls;
 mod drop_timer;
+mod environment;
 mod falling_piece;
 mod game;
+mod game_mode;
+mod game_state;
+mod garbage;
+mod ghost;
 mod grab_bag;
 mod grid;
+mod guides;
+mod headless;
+mod hint;
+mod hud;
+mod line_clear;
+mod menu;
+mod online;
+mod orbit_camera;
 mod piece;
+mod piece_motion;
+mod placement;
+mod player;
+mod protocol;
+mod puzzle;
+mod records;
+mod replay;
 mod rotation;
+mod save_game;
+mod scoring;
+mod server;
+mod spectator;
+mod theme;
+mod versus;
 
+pub use agent::*;
 pub use assets::*;
+pub use audio::*;
+pub use autoplay::*;
+pub use cli::*;
 pub use config::*;
+pub use config_reload::*;
 pub use controls::*;
 pub use drop_timer::*;
+pub use environment::*;
 pub use falling_piece::*;
 pub use game::*;
+pub use game_mode::*;
+pub use game_state::*;
+pub use garbage::*;
+pub use ghost::*;
 pub use grab_bag::*;
 pub use grid::*;
+pub use guides::*;
+pub use headless::*;
+pub use hint::*;
+pub use hud::*;
+pub use line_clear::*;
+pub use menu::*;
+pub use online::*;
+pub use orbit_camera::*;
 pub use piece::*;
+pub use piece_motion::*;
+pub use placement::*;
+pub use player::*;
+pub use protocol::*;
+pub use puzzle::*;
+pub use records::*;
+pub use replay::*;
 pub use rotation::*;
+pub use save_game::*;
+pub use scoring::*;
+pub use server::*;
+pub use spectator::*;
+pub use theme::*;
+pub use versus::*;
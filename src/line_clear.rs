@@ -0,0 +1,202 @@
+use crate::{
+    cell_translation, Board, Config, FallingPiece, FallingPieceAction, FallingPieceEvent,
+    FallingPieceFeedback, FallingPieceOutcome, Grid, GridCell, GridSide, Player, SceneAssets,
+};
+use bevy::prelude::*;
+
+/// Sent once for every grid that fills one or more rows when the falling piece locks into it.
+///
+/// `rows` are the indices of the full rows *before* they are collapsed, in ascending order.
+#[derive(Clone, Debug, Event)]
+pub struct LinesCleared {
+    pub grid: Entity,
+    pub player: Player,
+    pub side: GridSide,
+    pub rows: Vec<i32>,
+    pub count: usize,
+}
+
+/// Sent when a piece that cleared rows in any of the player's grids has locked in all of them. A piece can lock into
+/// each grid on a different frame, so its clears are gathered until it's placed.
+#[derive(Clone, Debug, Event)]
+pub struct PieceCleared {
+    pub player: Player,
+    /// `(side, count)` for each grid with cleared rows.
+    pub clears: Vec<(GridSide, usize)>,
+}
+
+/// The clears of the falling piece in the grids it has already locked into, as `(side, count)` pairs, waiting for it to
+/// lock into the rest. Kept on the `Board` so that they're saved along with it.
+#[derive(Clone, Component, Debug, Default)]
+pub struct PendingClears(pub Vec<(GridSide, usize)>);
+
+/// Whether one piece's clears, as `(side, count)` pairs, were in both of the player's grids.
+pub fn is_dual_clear(clears: &[(GridSide, usize)]) -> bool {
+    let cleared_side = |side| clears.iter().any(|(s, _)| *s == side);
+
+    cleared_side(GridSide::Left) && cleared_side(GridSide::Right)
+}
+
+/// The full rows of a grid first flash, then the rows above them collapse down to fill the gap.
+#[derive(Component)]
+pub struct LineClearAnimation {
+    rows: Vec<i32>,
+    flash: Timer,
+    collapse: Timer,
+}
+
+impl LineClearAnimation {
+    pub fn new(rows: Vec<i32>, flash_time: f32, collapse_time: f32) -> Self {
+        Self {
+            rows,
+            flash: Timer::from_seconds(flash_time, TimerMode::Once),
+            collapse: Timer::from_seconds(collapse_time, TimerMode::Once),
+        }
+    }
+
+    fn tick(&mut self, delta: std::time::Duration) {
+        if self.flash.finished() {
+            self.collapse.tick(delta);
+        } else {
+            self.flash.tick(delta);
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.flash.finished() && self.collapse.finished()
+    }
+
+    fn flash_is_lit(&self) -> bool {
+        const FLASHES_PER_SECOND: f32 = 10.0;
+
+        !self.flash.finished() && (self.flash.elapsed_secs() * FLASHES_PER_SECOND) as i32 % 2 == 0
+    }
+
+    fn num_cleared_rows_below(&self, row: i32) -> usize {
+        self.rows.iter().filter(|r| **r < row).count()
+    }
+}
+
+pub fn send_lines_cleared_events(
+    mut grid_query: Query<(Entity, &mut Grid)>,
+    mut events: EventWriter<LinesCleared>,
+) {
+    for (grid_entity, mut grid) in grid_query.iter_mut() {
+        let rows = grid.take_unreported_full_rows();
+        if !rows.is_empty() {
+            events.send(LinesCleared {
+                grid: grid_entity,
+                player: grid.player(),
+                side: grid.side(),
+                count: rows.len(),
+                rows,
+            });
+        }
+    }
+}
+
+/// Should run after `send_lines_cleared_events`, so a piece's last clears are reported in the frame that it's placed.
+pub fn send_piece_cleared_events(
+    mut lines_cleared: EventReader<LinesCleared>,
+    mut feedback: EventReader<FallingPieceFeedback>,
+    mut board_query: Query<(&Player, &mut PendingClears), With<Board>>,
+    mut events: EventWriter<PieceCleared>,
+) {
+    for event in lines_cleared.read() {
+        if let Some((_, mut pending)) = board_query.iter_mut().find(|(p, _)| **p == event.player) {
+            pending.0.push((event.side, event.count));
+        }
+    }
+
+    for event in feedback.read() {
+        if event.outcome != FallingPieceOutcome::Placed {
+            continue;
+        }
+        let Some((_, mut pending)) = board_query.iter_mut().find(|(p, _)| **p == event.player)
+        else {
+            continue;
+        };
+        let clears = std::mem::take(&mut pending.0);
+        if !clears.is_empty() {
+            events.send(PieceCleared {
+                player: event.player,
+                clears,
+            });
+        }
+    }
+}
+
+pub fn start_line_clear_animations(
+    config: Res<Config>,
+    mut commands: Commands,
+    mut events: EventReader<LinesCleared>,
+) {
+    for event in events.read() {
+        commands.entity(event.grid).insert(LineClearAnimation::new(
+            event.rows.clone(),
+            config.line_clear_flash_time,
+            config.line_clear_collapse_time,
+        ));
+    }
+}
+
+pub fn animate_line_clears(
+    time: Res<Time>,
+    assets: Res<SceneAssets>,
+    mut commands: Commands,
+    mut grid_query: Query<(Entity, &mut Grid, &mut LineClearAnimation)>,
+    mut cell_query: Query<(&mut Transform, &mut MeshMaterial3d<StandardMaterial>), With<GridCell>>,
+    falling_piece_query: Query<&FallingPiece>,
+    mut piece_events: EventWriter<FallingPieceEvent>,
+) {
+    let mut finished_players = Vec::new();
+    let mut animating_players = Vec::new();
+    for (grid_entity, mut grid, mut animation) in grid_query.iter_mut() {
+        animation.tick(time.delta());
+
+        if animation.finished() {
+            for p in grid_cell_points(&grid) {
+                let (mut tfm, _) = cell_query.get_mut(grid.cell_entity(p)).unwrap();
+                *tfm = Transform::from_translation(cell_translation(p));
+            }
+            grid.collapse_full_rows();
+            commands.entity(grid_entity).remove::<LineClearAnimation>();
+            finished_players.push(grid.player());
+            continue;
+        }
+        animating_players.push(grid.player());
+
+        let lit = animation.flash_is_lit();
+        let collapse_t = animation.collapse.fraction();
+        for p in grid_cell_points(&grid) {
+            let (mut tfm, mut material) = cell_query.get_mut(grid.cell_entity(p)).unwrap();
+            let is_cleared_row = animation.rows.contains(&p.y);
+            if is_cleared_row {
+                if lit {
+                    material.0 = assets.piece_materials.line_clear_material();
+                }
+                tfm.scale.y = 1.0 - collapse_t;
+            } else {
+                let fall = animation.num_cleared_rows_below(p.y) as f32 * collapse_t;
+                tfm.translation.y = cell_translation(p).y - fall;
+            }
+        }
+    }
+
+    // The piece that caused the clears was despawned when it locked, so it's our job to spawn the next one once all of
+    // the player's grids are done.
+    finished_players.sort();
+    finished_players.dedup();
+    for player in finished_players {
+        let still_animating = animating_players.contains(&player);
+        let has_piece = falling_piece_query.iter().any(|p| p.player() == player);
+        if !still_animating && !has_piece {
+            piece_events.send(FallingPieceEvent::new(player, FallingPieceAction::Spawn));
+        }
+    }
+}
+
+fn grid_cell_points(grid: &Grid) -> impl Iterator<Item = IVec2> {
+    let (width, height) = (grid.width(), grid.height());
+    (0..height).flat_map(move |y| (0..width).map(move |x| IVec2::new(x, y)))
+}
@@ -1,38 +1,569 @@
-use bevy::prelude::*;
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    asset::io::{AssetSource, AssetSourceBuilder},
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::{ExitCondition, WindowMode},
+    winit::WinitPlugin,
+};
 use projectris::{
-    create_game, create_scene_assets, send_drop_piece_events, send_move_piece_events,
-    synchronize_grid_materials, update_falling_piece, Config, FallingPieceEvent,
+    add_placement_hints, animate_line_clears, animate_piece_motion, apply_config_changes,
+    autoplaying, broadcast_boards, broadcast_game_start, check_game_mode_goal, check_puzzle_goal,
+    connect_as_spectator, connect_to_server, create_camera, create_game, create_scene_assets,
+    disconnect_from_server, draw_projection_guides, enter_game_over, enter_playing,
+    finish_headless_replay, fly_free_camera, game_can_be_saved, game_exists, game_interrupted,
+    handle_menu_buttons, layout_hud, leave_online_game, load_config_asset, load_orbit_camera,
+    load_personal_best, load_puzzle_library, load_records, load_sound_bank, move_orbit_camera,
+    pause_time, play_autoplay, play_replay, play_sounds, poll_config_file, raise_garbage,
+    receive_online_messages, receive_spectated_game, record_finished_game, record_replay_moves,
+    remove_saved_game, report_headless_results, reset_free_camera, resume_saved_game, save_game,
+    save_replay, send_drop_piece_events, send_gameplay_sounds, send_lines_cleared_events,
+    send_move_piece_events, send_online_updates, send_piece_cleared_events, send_versus_garbage,
+    set_up_dig, set_up_puzzle, set_up_versus, show_placement_hints, spawn_config_status,
+    spawn_game_over_menu, spawn_high_scores_menu, spawn_hud, spawn_lobby_menu, spawn_main_menu,
+    spawn_pause_menu, spawn_profiles_menu, spawn_puzzle_select_menu, spawn_spectator_overlay,
+    speed_up_drop_timer, split_screen, start_autoplay, start_line_clear_animations, start_music,
+    start_replay_playback, start_replay_recording, synchronize_grid_materials, teardown_game,
+    toggle_hints, toggle_pause, type_profile_name, unpause_time, update_config_status,
+    update_falling_piece, update_game_stats, update_ghost_pieces, update_hud, update_lobby_status,
+    update_profile_list, wait_for_opponent, watch_game, ActivePuzzle, AutoplayPlayers, Broadcaster,
+    Cli, Config, ConfigAsset, ConfigLoader, ConfigSource, ConfigStatus, FallingPieceEvent,
+    FallingPieceFeedback, FixedSeed, GameOver, GameState, Headless, HintsEnabled, LevelUp,
+    LinesCleared, LobbyStatus, OnlineClient, OnlineGame, OnlineServer, OutgoingGarbage,
+    PieceCleared, PlaySound, ReplayFile, ReplayPlayback, ReplayRecorder, RestartGame, ResumeGame,
+    SelectedPuzzle, SpectatedServer, SpectatorClient, StartWatching, CONFIG_SOURCE, USAGE,
 };
+use std::time::Duration;
 
-fn main() -> Result<(), ron::Error> {
-    let default_plugins = DefaultPlugins.set(WindowPlugin {
-        primary_window: Some(Window {
-            title: "Projectris".into(),
-            name: Some("projectris.app".into()),
-            resolution: (800., 600.).into(),
-            // Tells Wasm to resize the window according to the available canvas
-            fit_canvas_to_parent: true,
-            // Tells Wasm not to override default event handling, like F5, Ctrl+R etc.
-            prevent_default_event_handling: false,
-            ..default()
-        }),
-        ..default()
+fn main() {
+    let cli = Cli::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+    if cli.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let replay = cli.replay.as_ref().map(|path| {
+        ReplayFile::read_file(path).unwrap_or_else(|e| {
+            eprintln!("Couldn't read the replay {}: {}", path.display(), e);
+            std::process::exit(2);
+        })
+    });
+    let mut config_source = ConfigSource {
+        path: cli.config_path.clone(),
+        overrides: cli.overrides.clone(),
+    };
+    // A replay only plays out the same on the same grids and pieces.
+    if let Some(replay) = &replay {
+        let [width, height] = replay.grid_size;
+        config_source.overrides.extend([
+            ("grid_size".to_string(), format!("({}, {})", width, height)),
+            (
+                "repeats_per_bag".to_string(),
+                replay.repeats_per_bag.to_string(),
+            ),
+        ]);
+    }
+
+    // `--check-config` only reports the problems with the config, for checking it without starting the game.
+    if cli.check_config {
+        check_config(&config_source);
+    }
+
+    let config = read_config(&config_source);
+
+    // `--mode` skips the main menu, and so do replays and headless runs, which have no one to click through it.
+    // `--connect` goes straight to the online lobby instead, and `--spectate` watches games on a server. With
+    // `--broadcast`, local games can be watched.
+    let start_mode = match &replay {
+        Some(replay) => Some(replay.mode),
+        None if cli.headless => Some(cli.mode.unwrap_or_default()),
+        None => cli.mode,
+    };
+    let seed = replay.as_ref().map(|r| r.seed).or(cli.seed);
+    let server = cli.connect.clone().map(OnlineServer);
+    let spectated_server = cli.spectate.clone().map(SpectatedServer);
+    let broadcaster = cli.broadcast.as_ref().map(|address| {
+        Broadcaster::connect(address, config.grid_size).unwrap_or_else(|e| {
+            eprintln!("Couldn't connect to {}: {}", address, e);
+            std::process::exit(2);
+        })
     });
+    let start_state = if spectated_server.is_some() {
+        GameState::Spectating
+    } else if server.is_some() {
+        GameState::Lobby
+    } else if start_mode.is_some() {
+        GameState::Playing
+    } else {
+        GameState::MainMenu
+    };
 
-    App::new()
-        .add_event::<FallingPieceEvent>()
-        .insert_resource(Config::read_file("config.ron")?)
-        .add_plugins(default_plugins)
+    let playing = in_state(GameState::Playing);
+    let spectating = in_state(GameState::Spectating);
+    let online = resource_exists::<OnlineClient>;
+    // Online games can already be watched through the server.
+    let broadcasting = resource_exists::<Broadcaster>.and(not(resource_exists::<OnlineGame>));
+    // A replay takes the place of the controls, the bot and gravity.
+    let controlled = not(resource_exists::<ReplayPlayback>);
+    let recording = resource_exists::<ReplayRecorder>;
+
+    let mut app = App::new();
+    app.register_asset_source(
+        CONFIG_SOURCE,
+        AssetSourceBuilder::default().with_reader(AssetSource::get_default_reader(
+            config_source.dir().to_string_lossy().into_owned(),
+        )),
+    );
+    if cli.headless {
+        app.add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+        )
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+        .insert_resource(Headless)
+        .add_systems(OnEnter(GameState::GameOver), report_headless_results)
+        .add_systems(
+            Update,
+            finish_headless_replay.run_if(resource_exists::<ReplayPlayback>),
+        );
+    } else {
+        let [width, height] = cli.window_size.unwrap_or([800, 600]);
+        app.add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: "Projectris".into(),
+                name: Some("projectris.app".into()),
+                resolution: (width as f32, height as f32).into(),
+                mode: if cli.fullscreen {
+                    WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+                } else {
+                    WindowMode::Windowed
+                },
+                // Tells Wasm to resize the window according to the available canvas
+                fit_canvas_to_parent: true,
+                // Tells Wasm not to override default event handling, like F5, Ctrl+R etc.
+                prevent_default_event_handling: false,
+                ..default()
+            }),
+            ..default()
+        }));
+    }
+    if let Some(server) = server {
+        app.insert_resource(server);
+    }
+    if let Some(spectated_server) = spectated_server {
+        app.insert_resource(spectated_server);
+    }
+    if let Some(broadcaster) = broadcaster {
+        app.insert_resource(broadcaster);
+    }
+    if let Some(players) = cli.autoplay {
+        app.insert_resource(AutoplayPlayers(players));
+    }
+    if let Some(seed) = seed {
+        app.insert_resource(FixedSeed(seed));
+    }
+    if let Some(replay) = &replay {
+        app.insert_resource(ReplayPlayback::new(replay));
+    }
+    if let Some(path) = cli.record {
+        app.insert_resource(ReplayRecorder::new(path));
+    }
+
+    app.add_event::<FallingPieceEvent>()
+        .add_event::<FallingPieceFeedback>()
+        .add_event::<GameOver>()
+        .add_event::<LevelUp>()
+        .add_event::<LinesCleared>()
+        .add_event::<OutgoingGarbage>()
+        .add_event::<PieceCleared>()
+        .add_event::<PlaySound>()
+        .add_event::<RestartGame>()
+        .add_event::<StartWatching>()
+        .insert_resource(config)
+        .insert_resource(config_source)
+        .insert_resource(start_mode.unwrap_or_default())
+        .init_resource::<SelectedPuzzle>()
+        .init_resource::<HintsEnabled>()
+        .init_resource::<ConfigStatus>()
+        .init_asset::<ConfigAsset>()
+        .init_asset_loader::<ConfigLoader>()
+        // The first state is entered before `Startup`, so anywhere past the main menu is only entered once the scene
+        // assets exist.
+        .insert_state(GameState::default())
+        .add_systems(
+            Startup,
+            move |mut next_state: ResMut<NextState<GameState>>| {
+                if start_state != GameState::default() {
+                    next_state.set(start_state);
+                }
+            },
+        )
+        .enable_state_scoped_entities::<GameState>()
         .add_systems(Startup, create_scene_assets)
-        .add_systems(Startup, create_game.after(create_scene_assets))
-        .add_systems(Update, send_drop_piece_events)
-        .add_systems(Update, send_move_piece_events)
-        .add_systems(Update, update_falling_piece)
+        .add_systems(Startup, create_camera)
+        .add_systems(Startup, load_orbit_camera)
+        .add_systems(Startup, (load_config_asset, spawn_config_status))
+        .add_systems(
+            Update,
+            (poll_config_file, apply_config_changes, update_config_status).chain(),
+        )
+        .add_systems(Startup, load_sound_bank)
+        .add_systems(Startup, load_records)
+        .add_systems(Startup, load_puzzle_library)
+        .add_systems(Startup, start_music.after(load_sound_bank))
+        .add_systems(
+            OnEnter(GameState::MainMenu),
+            (
+                save_game
+                    .before(teardown_game)
+                    .before(spawn_main_menu)
+                    .run_if(game_can_be_saved),
+                teardown_game,
+                leave_online_game,
+                spawn_main_menu,
+            ),
+        )
+        .add_systems(
+            OnEnter(GameState::Lobby),
+            (
+                teardown_game,
+                leave_online_game,
+                connect_to_server.after(leave_online_game),
+                spawn_lobby_menu,
+            ),
+        )
+        .add_systems(
+            OnEnter(GameState::Playing),
+            (
+                create_game,
+                set_up_puzzle.after(create_game),
+                set_up_dig.after(create_game),
+                set_up_versus.after(create_game),
+                start_autoplay
+                    .after(create_game)
+                    .run_if(resource_exists::<AutoplayPlayers>),
+                start_replay_playback
+                    .after(create_game)
+                    .run_if(resource_exists::<ReplayPlayback>),
+                resume_saved_game
+                    .after(set_up_puzzle)
+                    .after(set_up_dig)
+                    .after(set_up_versus)
+                    .run_if(resource_exists::<ResumeGame>),
+                start_replay_recording
+                    .after(create_game)
+                    .before(resume_saved_game)
+                    .run_if(recording),
+                add_placement_hints.after(create_game),
+                spawn_hud.after(create_game),
+                load_personal_best,
+                broadcast_game_start.run_if(broadcasting.clone()),
+            )
+                .run_if(not(game_exists)),
+        )
+        .add_systems(
+            OnEnter(GameState::Spectating),
+            (
+                teardown_game,
+                connect_as_spectator,
+                spawn_spectator_overlay,
+                reset_free_camera,
+            ),
+        )
+        .add_systems(OnEnter(GameState::Paused), (pause_time, spawn_pause_menu))
+        .add_systems(OnExit(GameState::Paused), unpause_time)
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            (
+                record_finished_game
+                    .run_if(controlled.clone())
+                    .run_if(not(autoplaying))
+                    .run_if(not(resource_exists::<Headless>)),
+                save_replay.run_if(recording),
+                remove_saved_game,
+                spawn_game_over_menu,
+                disconnect_from_server,
+            ),
+        )
+        .add_systems(OnEnter(GameState::HighScores), spawn_high_scores_menu)
+        .add_systems(OnEnter(GameState::Profiles), spawn_profiles_menu)
+        .add_systems(
+            OnEnter(GameState::PuzzleSelect),
+            (teardown_game, spawn_puzzle_select_menu),
+        )
+        .add_systems(
+            Update,
+            (update_profile_list, type_profile_name).run_if(in_state(GameState::Profiles)),
+        )
+        .add_systems(
+            Update,
+            (wait_for_opponent.run_if(online), update_lobby_status)
+                .run_if(in_state(GameState::Lobby))
+                .run_if(resource_exists::<LobbyStatus>),
+        )
+        .add_systems(
+            Update,
+            (
+                receive_spectated_game.run_if(resource_exists::<SpectatorClient>),
+                (teardown_game, watch_game, spawn_hud)
+                    .chain()
+                    .after(receive_spectated_game)
+                    .run_if(on_event::<StartWatching>),
+                update_lobby_status.run_if(resource_exists::<LobbyStatus>),
+                fly_free_camera,
+            )
+                .run_if(spectating.clone()),
+        )
+        // The opponent can't be paused, so neither can an online game.
+        .add_systems(
+            Update,
+            toggle_pause.run_if(not(resource_exists::<OnlineGame>)),
+        )
+        .add_systems(Update, (handle_menu_buttons, split_screen))
+        .add_systems(
+            Update,
+            (teardown_game, enter_playing)
+                .chain()
+                .run_if(on_event::<RestartGame>),
+        )
+        .add_systems(
+            Update,
+            (send_drop_piece_events, send_move_piece_events)
+                .run_if(playing.clone())
+                .run_if(controlled.clone()),
+        )
+        .add_systems(
+            Update,
+            play_autoplay
+                .before(update_falling_piece)
+                .run_if(playing.clone())
+                .run_if(controlled),
+        )
+        .add_systems(
+            Update,
+            (
+                play_replay.run_if(resource_exists::<ReplayPlayback>),
+                record_replay_moves
+                    .after(play_replay)
+                    .after(send_drop_piece_events)
+                    .after(send_move_piece_events)
+                    .after(play_autoplay)
+                    .run_if(recording),
+            )
+                .before(update_falling_piece)
+                .run_if(playing.clone()),
+        )
+        .add_systems(Update, update_falling_piece.run_if(playing.clone()))
+        .add_systems(Update, move_orbit_camera.run_if(playing.clone()))
+        .add_systems(
+            Update,
+            animate_piece_motion
+                .after(update_falling_piece)
+                .after(raise_garbage)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            draw_projection_guides
+                .after(animate_piece_motion)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            update_ghost_pieces
+                .after(animate_piece_motion)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            enter_game_over
+                .after(update_falling_piece)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            send_lines_cleared_events
+                .after(update_falling_piece)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            start_line_clear_animations
+                .after(send_lines_cleared_events)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            send_piece_cleared_events
+                .after(send_lines_cleared_events)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_hints,
+                show_placement_hints
+                    .after(toggle_hints)
+                    .after(update_falling_piece)
+                    .after(raise_garbage),
+            )
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            synchronize_grid_materials
+                .after(update_falling_piece)
+                .after(show_placement_hints)
+                .after(receive_spectated_game)
+                .run_if(playing.clone().or(spectating.clone())),
+        )
+        .add_systems(
+            Update,
+            animate_line_clears
+                .after(start_line_clear_animations)
+                .after(synchronize_grid_materials)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            update_game_stats
+                .after(update_falling_piece)
+                .after(send_piece_cleared_events)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            check_game_mode_goal
+                .after(update_game_stats)
+                .before(enter_game_over)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            check_puzzle_goal
+                .after(update_game_stats)
+                .before(enter_game_over)
+                .run_if(playing.clone())
+                .run_if(resource_exists::<ActivePuzzle>),
+        )
+        .add_systems(
+            Update,
+            raise_garbage
+                .after(update_falling_piece)
+                .before(synchronize_grid_materials)
+                .before(enter_game_over)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            send_versus_garbage
+                .after(send_piece_cleared_events)
+                .before(raise_garbage)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            receive_online_messages
+                .before(raise_garbage)
+                .before(update_hud)
+                .run_if(playing.clone())
+                .run_if(online),
+        )
+        .add_systems(
+            Update,
+            send_online_updates
+                .after(animate_line_clears)
+                .after(raise_garbage)
+                .after(send_versus_garbage)
+                .after(update_game_stats)
+                .after(enter_game_over)
+                .run_if(playing.clone())
+                .run_if(online),
+        )
+        .add_systems(
+            Update,
+            broadcast_boards
+                .after(animate_line_clears)
+                .after(raise_garbage)
+                .after(update_game_stats)
+                .after(enter_game_over)
+                .run_if(playing.clone())
+                .run_if(broadcasting),
+        )
         .add_systems(
             Update,
-            synchronize_grid_materials.after(update_falling_piece),
+            speed_up_drop_timer
+                .after(update_game_stats)
+                .run_if(playing.clone()),
+        )
+        .add_systems(
+            Update,
+            (update_hud, layout_hud)
+                .after(update_game_stats)
+                .after(receive_spectated_game)
+                .run_if(playing.clone().or(spectating)),
+        )
+        .add_systems(
+            Update,
+            send_gameplay_sounds
+                .after(update_falling_piece)
+                .after(send_piece_cleared_events)
+                .after(update_game_stats),
+        )
+        .add_systems(Update, play_sounds.after(send_gameplay_sounds))
+        // The game is saved whenever the player might not come back to it.
+        .add_systems(
+            Last,
+            save_game.run_if(game_interrupted).run_if(game_can_be_saved),
         )
         .run();
+}
+
+/// A broken config doesn't stop the game from starting. The error is shown on screen until it's fixed. Overrides from
+/// the command line still have to work, though.
+fn read_config(source: &ConfigSource) -> Config {
+    source.read().unwrap_or_else(|e| {
+        eprintln!(
+            "Couldn't use {}, using the default config instead:\n{}",
+            source.path.display(),
+            e
+        );
+        let mut config = Config::default();
+        if let Err(e) = source.apply(&mut config) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        config
+    })
+}
 
-    Ok(())
+fn check_config(source: &ConfigSource) -> ! {
+    match source.read() {
+        Ok(_) => {
+            println!("{} is valid", source.path.display());
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
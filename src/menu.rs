@@ -0,0 +1,481 @@
+use crate::{
+    format_time, Board, Config, GameMode, GameOverPlayer, GameOverReason, GameState, GameStats,
+    LobbyStatus, OnlineGame, OnlineServer, Player, PuzzleLibrary, Records, RestartGame, ResumeGame,
+    SavedGame, SelectedPuzzle, ALL_GAME_MODES,
+};
+use bevy::{
+    color::palettes::css,
+    input::keyboard::{Key, KeyboardInput},
+    prelude::*,
+};
+
+const HIGH_SCORES_PER_MODE: usize = 10;
+const MAX_PROFILE_NAME_LEN: usize = 16;
+
+#[derive(Clone, Copy, Component, Debug)]
+pub enum MenuButton {
+    Continue,
+    Play(GameMode),
+    Puzzles,
+    PlayPuzzle(usize),
+    PlayOnline,
+    Resume,
+    Restart,
+    MainMenu,
+    HighScores,
+    Profiles,
+    SelectProfile(usize),
+    Quit,
+}
+
+impl MenuButton {
+    fn label(&self) -> &'static str {
+        match self {
+            MenuButton::Continue => "Continue",
+            MenuButton::Play(mode) => mode.name(),
+            MenuButton::Puzzles => "Puzzles",
+            MenuButton::PlayPuzzle(_) => "Play",
+            MenuButton::PlayOnline => "Play Online",
+            MenuButton::Resume => "Resume",
+            MenuButton::Restart => "Restart",
+            MenuButton::MainMenu => "Main Menu",
+            MenuButton::HighScores => "High Scores",
+            MenuButton::Profiles => "Profiles",
+            MenuButton::SelectProfile(_) => "Select",
+            MenuButton::Quit => "Quit",
+        }
+    }
+}
+
+const BUTTON_COLOR: Srgba = css::DARK_SLATE_GRAY;
+const HOVERED_BUTTON_COLOR: Srgba = css::SLATE_GRAY;
+const PRESSED_BUTTON_COLOR: Srgba = css::LIGHT_SLATE_GRAY;
+
+/// Online play is only offered when there's a server to connect to, and Continue when there's a saved game.
+pub fn spawn_main_menu(commands: Commands, server: Option<Res<OnlineServer>>) {
+    let mut buttons = Vec::new();
+    if SavedGame::exists() {
+        buttons.push(MenuButton::Continue);
+    }
+    buttons.extend_from_slice(&[
+        MenuButton::Play(GameMode::Endless),
+        MenuButton::Play(GameMode::Sprint),
+        MenuButton::Play(GameMode::Ultra),
+        MenuButton::Play(GameMode::Marathon),
+        MenuButton::Play(GameMode::Dig),
+        MenuButton::Play(GameMode::Versus),
+    ]);
+    if server.is_some() {
+        buttons.push(MenuButton::PlayOnline);
+    }
+    buttons.extend_from_slice(&[
+        MenuButton::Puzzles,
+        MenuButton::HighScores,
+        MenuButton::Profiles,
+        MenuButton::Quit,
+    ]);
+
+    spawn_menu(commands, GameState::MainMenu, "Projectris", &buttons);
+}
+
+pub fn spawn_pause_menu(commands: Commands) {
+    spawn_menu(
+        commands,
+        GameState::Paused,
+        "Paused",
+        &[
+            MenuButton::Resume,
+            MenuButton::Restart,
+            MenuButton::MainMenu,
+            MenuButton::Quit,
+        ],
+    );
+}
+
+pub fn spawn_game_over_menu(
+    commands: Commands,
+    mode: Res<GameMode>,
+    reason: Res<GameOverReason>,
+    game_over_player: Res<GameOverPlayer>,
+    online: Option<Res<OnlineGame>>,
+    board_query: Query<(&Player, &GameStats), With<Board>>,
+) {
+    let mut boards: Vec<(&Player, &GameStats)> = board_query.iter().collect();
+    boards.sort_by_key(|(player, _)| **player);
+
+    let (title, summaries): (String, Vec<String>) = if mode.players().len() > 1 {
+        // Whoever ended a versus game by topping out lost it.
+        let winner = game_over_player.0.opponent();
+        let summaries = boards
+            .iter()
+            .map(|(player, stats)| {
+                format!(
+                    "{}: {} lines, score {}",
+                    player.name(),
+                    stats.total_lines(),
+                    stats.score()
+                )
+            })
+            .collect();
+        let title = if *reason == GameOverReason::Disconnected {
+            "Disconnected".to_string()
+        } else {
+            format!("{} Wins!", winner.name())
+        };
+        (title, summaries)
+    } else {
+        let title = match *reason {
+            GameOverReason::ToppedOut => "Game Over",
+            GameOverReason::Completed => "Finished!",
+            GameOverReason::OutOfPieces => "Out of Pieces",
+            GameOverReason::Disconnected => "Disconnected",
+        };
+        let summaries = boards
+            .iter()
+            .map(|(_, stats)| {
+                if mode.ranks_by_time() && *reason == GameOverReason::Completed {
+                    format!("Time: {}", format_time(stats.elapsed_secs()))
+                } else {
+                    format!("Score: {}", stats.score())
+                }
+            })
+            .collect();
+        (title.to_string(), summaries)
+    };
+
+    spawn_screen(commands, GameState::GameOver, &title, |parent| {
+        for summary in summaries {
+            parent.spawn(Text::new(summary));
+        }
+        // An online game can't be restarted on its own, so look for a new opponent instead.
+        let play_again = if online.is_some() {
+            MenuButton::PlayOnline
+        } else {
+            MenuButton::Restart
+        };
+        for button in [play_again, MenuButton::MainMenu, MenuButton::Quit] {
+            spawn_button(parent, button, button.label());
+        }
+    });
+}
+
+#[derive(Component)]
+pub struct LobbyStatusText;
+
+/// The status is filled in by `update_lobby_status`.
+pub fn spawn_lobby_menu(commands: Commands) {
+    spawn_screen(commands, GameState::Lobby, "Play Online", |parent| {
+        parent.spawn((LobbyStatusText, Text::default()));
+        spawn_button(parent, MenuButton::MainMenu, MenuButton::MainMenu.label());
+    });
+}
+
+/// Leaves the boards visible, unlike the other screens.
+pub fn spawn_spectator_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            StateScoped(GameState::Spectating),
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(12.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((LobbyStatusText, Text::default()));
+            parent.spawn((
+                Text::new("WASD, Q and E to fly, right drag to look, scroll to zoom, R to reset"),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+pub fn update_lobby_status(
+    status: Res<LobbyStatus>,
+    mut text_query: Query<(&mut Text, Ref<LobbyStatusText>)>,
+) {
+    for (mut text, marker) in text_query.iter_mut() {
+        if status.is_changed() || marker.is_added() {
+            text.0 = status.0.clone();
+        }
+    }
+}
+
+pub fn spawn_high_scores_menu(commands: Commands, config: Res<Config>, records: Res<Records>) {
+    spawn_screen(commands, GameState::HighScores, "High Scores", |parent| {
+        for mode in ALL_GAME_MODES
+            .iter()
+            .cloned()
+            .filter(|m| m.has_high_scores())
+        {
+            parent.spawn((
+                Text::new(mode.name()),
+                TextFont {
+                    font_size: 28.0,
+                    ..default()
+                },
+            ));
+            let high_scores = records.high_scores(mode, mode.goal(&config), HIGH_SCORES_PER_MODE);
+            if high_scores.is_empty() {
+                parent.spawn(Text::new("No games yet"));
+            }
+            for (i, game) in high_scores.into_iter().enumerate() {
+                let result = if mode.ranks_by_time() {
+                    format_time(game.duration_secs)
+                } else {
+                    game.score.to_string()
+                };
+                parent.spawn(Text::new(format!(
+                    "{}. {}  {}  ({} lines, level {}, {} pieces)",
+                    i + 1,
+                    game.profile,
+                    result,
+                    game.lines,
+                    game.level,
+                    game.pieces_placed
+                )));
+            }
+        }
+        spawn_button(parent, MenuButton::MainMenu, MenuButton::MainMenu.label());
+    });
+}
+
+pub fn spawn_puzzle_select_menu(
+    commands: Commands,
+    library: Res<PuzzleLibrary>,
+    records: Res<Records>,
+) {
+    spawn_screen(commands, GameState::PuzzleSelect, "Puzzles", |parent| {
+        if library.puzzles().is_empty() {
+            parent.spawn(Text::new("No puzzles found"));
+        }
+        for (i, puzzle) in library.puzzles().iter().enumerate() {
+            let label = if records.puzzle_solved(&puzzle.name) {
+                format!("{} (solved)", puzzle.name)
+            } else {
+                puzzle.name.clone()
+            };
+            spawn_button(parent, MenuButton::PlayPuzzle(i), &label);
+        }
+        spawn_button(parent, MenuButton::MainMenu, MenuButton::MainMenu.label());
+    });
+}
+
+/// The list of profiles is filled in by `update_profile_list`.
+#[derive(Component)]
+pub struct ProfileList;
+
+#[derive(Component)]
+pub struct NewProfileNameText;
+
+/// What the player has typed so far on the profiles screen.
+#[derive(Default, Resource)]
+pub struct NewProfileName(String);
+
+pub fn spawn_profiles_menu(mut commands: Commands) {
+    commands.insert_resource(NewProfileName::default());
+    spawn_screen(commands, GameState::Profiles, "Profiles", |parent| {
+        parent.spawn((
+            ProfileList,
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+        ));
+        parent.spawn(Text::new("Type a name and press Enter to add a profile"));
+        parent.spawn((NewProfileNameText, Text::new("_")));
+        spawn_button(parent, MenuButton::MainMenu, MenuButton::MainMenu.label());
+    });
+}
+
+pub fn update_profile_list(
+    mut commands: Commands,
+    records: Res<Records>,
+    list_query: Query<(Entity, Ref<ProfileList>)>,
+) {
+    for (list_entity, list) in list_query.iter() {
+        if !records.is_changed() && !list.is_added() {
+            continue;
+        }
+
+        let active_profile = records.active_profile();
+        commands
+            .entity(list_entity)
+            .despawn_descendants()
+            .with_children(|parent| {
+                for (i, profile) in records.profiles().iter().enumerate() {
+                    let label = if profile.name == active_profile {
+                        format!("> {} <", profile.name)
+                    } else {
+                        profile.name.clone()
+                    };
+                    spawn_button(parent, MenuButton::SelectProfile(i), &label);
+                }
+            });
+    }
+}
+
+pub fn type_profile_name(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut name: ResMut<NewProfileName>,
+    mut records: ResMut<Records>,
+    mut text_query: Query<&mut Text, With<NewProfileNameText>>,
+) {
+    for event in keyboard_events.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(c) => {
+                for ch in c.chars() {
+                    let allowed = ch.is_alphanumeric() || ch == ' ' || ch == '_' || ch == '-';
+                    if allowed && name.0.chars().count() < MAX_PROFILE_NAME_LEN {
+                        name.0.push(ch);
+                    }
+                }
+            }
+            Key::Space if name.0.chars().count() < MAX_PROFILE_NAME_LEN => name.0.push(' '),
+            Key::Backspace => {
+                name.0.pop();
+            }
+            Key::Enter if records.add_profile(&name.0) => name.0.clear(),
+            _ => {}
+        }
+    }
+
+    if name.is_changed() {
+        for mut text in text_query.iter_mut() {
+            text.0 = format!("{}_", name.0);
+        }
+    }
+}
+
+fn spawn_menu(commands: Commands, state: GameState, title: &str, buttons: &[MenuButton]) {
+    spawn_screen(commands, state, title, |parent| {
+        for button in buttons.iter().cloned() {
+            spawn_button(parent, button, button.label());
+        }
+    });
+}
+
+/// The screen is despawned automatically when leaving `state`.
+fn spawn_screen(
+    mut commands: Commands,
+    state: GameState,
+    title: &str,
+    spawn_contents: impl FnOnce(&mut ChildBuilder),
+) {
+    commands
+        .spawn((
+            StateScoped(state),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(title),
+                TextFont {
+                    font_size: 48.0,
+                    ..default()
+                },
+            ));
+            spawn_contents(parent);
+        });
+}
+
+fn spawn_button(parent: &mut ChildBuilder, button: MenuButton, label: &str) {
+    parent
+        .spawn((
+            Button,
+            button,
+            Node {
+                width: Val::Px(200.0),
+                height: Val::Px(48.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(BUTTON_COLOR.into()),
+        ))
+        .with_children(|parent| {
+            parent.spawn(Text::new(label));
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_menu_buttons(
+    mut interaction_query: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut mode: ResMut<GameMode>,
+    mut selected_puzzle: ResMut<SelectedPuzzle>,
+    mut records: ResMut<Records>,
+    mut restart_events: EventWriter<RestartGame>,
+    mut exit_events: EventWriter<AppExit>,
+    mut commands: Commands,
+) {
+    for (interaction, button, mut background) in interaction_query.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                background.0 = PRESSED_BUTTON_COLOR.into();
+                match button {
+                    MenuButton::Continue => {
+                        if let Some(saved) = SavedGame::load() {
+                            *mode = saved.mode;
+                            commands.insert_resource(ResumeGame(saved));
+                            next_state.set(GameState::Playing);
+                        }
+                    }
+                    MenuButton::Play(new_mode) => {
+                        *mode = *new_mode;
+                        next_state.set(GameState::Playing);
+                    }
+                    MenuButton::Puzzles => next_state.set(GameState::PuzzleSelect),
+                    MenuButton::PlayPuzzle(i) => {
+                        *mode = GameMode::Puzzle;
+                        selected_puzzle.0 = *i;
+                        next_state.set(GameState::Playing);
+                    }
+                    MenuButton::PlayOnline => next_state.set(GameState::Lobby),
+                    MenuButton::Resume => next_state.set(GameState::Playing),
+                    MenuButton::Restart => {
+                        restart_events.send(RestartGame);
+                    }
+                    MenuButton::MainMenu => next_state.set(GameState::MainMenu),
+                    MenuButton::HighScores => next_state.set(GameState::HighScores),
+                    MenuButton::Profiles => next_state.set(GameState::Profiles),
+                    MenuButton::SelectProfile(i) => {
+                        if let Some(profile) = records.profiles().get(*i) {
+                            let name = profile.name.clone();
+                            records.select_profile(&name);
+                        }
+                    }
+                    MenuButton::Quit => {
+                        exit_events.send(AppExit::Success);
+                    }
+                }
+            }
+            Interaction::Hovered => background.0 = HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => background.0 = BUTTON_COLOR.into(),
+        }
+    }
+}
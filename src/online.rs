@@ -0,0 +1,323 @@
+use crate::{
+    cube_pbr, cube_translation, Board, BoardSnapshot, ClientMessage, Config, Connection,
+    FallingPiece, GameMode, GameOver, GameOverReason, GameState, GameStats, GarbageRise, Grid,
+    GridSide, OutgoingGarbage, PieceSnapshot, Player, SceneAssets, ServerMessage, PROTOCOL_VERSION,
+};
+use bevy::prelude::*;
+
+/// The server to play online games on, from `--connect`.
+#[derive(Resource)]
+pub struct OnlineServer(pub String);
+
+/// The connection to the server, from joining the lobby until the end of the game.
+#[derive(Resource)]
+pub struct OnlineClient {
+    connection: Connection,
+    last_snapshot: Option<BoardSnapshot>,
+}
+
+/// Set up by the server when an opponent is found.
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct OnlineGame {
+    pub local_player: Player,
+    pub seed: u64,
+}
+
+/// Marks a board that's only a copy of the snapshots sent from another machine, e.g. the opponent's board in an online
+/// game. Nothing is simulated for it here.
+#[derive(Component, Default)]
+pub struct Remote {
+    incoming_garbage: u32,
+}
+
+impl Remote {
+    pub fn incoming_garbage(&self) -> u32 {
+        self.incoming_garbage
+    }
+
+    /// Takes the numbers from a snapshot. `show_board_snapshot` takes care of what's on the board.
+    pub fn update(&mut self, snapshot: &BoardSnapshot, stats: &mut GameStats) {
+        *stats = snapshot.stats.clone();
+        self.incoming_garbage = snapshot.incoming_garbage;
+    }
+}
+
+/// The cubes of a falling piece on another machine, drawn from a `BoardSnapshot`.
+#[derive(Component)]
+pub struct SnapshotPiece;
+
+/// Takes a snapshot of one player's board, so it can be drawn on another machine.
+pub fn board_snapshot(
+    player: Player,
+    stats: &GameStats,
+    incoming_garbage: u32,
+    grid_query: &Query<&Grid>,
+    falling_piece_query: &Query<&FallingPiece>,
+) -> BoardSnapshot {
+    let cells = |side: GridSide| {
+        grid_query
+            .iter()
+            .find(|g| g.player() == player && g.side() == side)
+            .map_or_else(Vec::new, |g| g.visible_cells().to_vec())
+    };
+    let piece = falling_piece_query
+        .iter()
+        .find(|p| p.player() == player)
+        .map(|p| PieceSnapshot {
+            piece_type: p.piece_type(),
+            cell_positions: p.cell_positions(),
+        });
+
+    BoardSnapshot {
+        left: cells(GridSide::Left),
+        right: cells(GridSide::Right),
+        stats: stats.clone(),
+        incoming_garbage,
+        piece,
+    }
+}
+
+/// Draws the grids and falling piece of a snapshot on one player's board. The board's stats are left to the caller.
+pub fn show_board_snapshot(
+    player: Player,
+    snapshot: &BoardSnapshot,
+    grid_query: &mut Query<&mut Grid>,
+    snapshot_piece_query: &Query<(Entity, &Player), With<SnapshotPiece>>,
+    scene_assets: &SceneAssets,
+    commands: &mut Commands,
+) {
+    for mut grid in grid_query.iter_mut().filter(|g| g.player() == player) {
+        let cells = match grid.side() {
+            GridSide::Left => &snapshot.left,
+            GridSide::Right => &snapshot.right,
+        };
+        grid.set_visible_cells(cells);
+    }
+
+    for (entity, _) in snapshot_piece_query.iter().filter(|(_, p)| **p == player) {
+        commands.entity(entity).despawn();
+    }
+    if let Some(piece) = &snapshot.piece {
+        for p in piece.cell_positions.iter() {
+            commands.spawn((
+                SnapshotPiece,
+                player,
+                cube_pbr(
+                    piece.piece_type,
+                    cube_translation(player, *p),
+                    &scene_assets.piece_materials,
+                    scene_assets.cube_mesh.clone(),
+                ),
+            ));
+        }
+    }
+}
+
+/// Shown in the lobby while waiting for an opponent.
+#[derive(Resource)]
+pub struct LobbyStatus(pub String);
+
+pub fn connect_to_server(config: Res<Config>, server: Res<OnlineServer>, mut commands: Commands) {
+    match Connection::connect(&server.0) {
+        Ok(mut connection) => {
+            connection.send(&ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                grid_size: config.grid_size,
+            });
+            commands.insert_resource(OnlineClient {
+                connection,
+                last_snapshot: None,
+            });
+            commands.insert_resource(LobbyStatus(format!(
+                "Waiting for an opponent on {}",
+                server.0
+            )));
+        }
+        Err(e) => {
+            commands.insert_resource(LobbyStatus(format!(
+                "Couldn't connect to {}: {}",
+                server.0, e
+            )));
+        }
+    }
+}
+
+/// Closes the connection, but keeps the `OnlineGame` around so the game over menu knows where it came from.
+pub fn disconnect_from_server(mut commands: Commands) {
+    commands.remove_resource::<OnlineClient>();
+}
+
+pub fn leave_online_game(mut commands: Commands) {
+    commands.remove_resource::<OnlineClient>();
+    commands.remove_resource::<OnlineGame>();
+}
+
+pub fn wait_for_opponent(
+    mut client: ResMut<OnlineClient>,
+    mut mode: ResMut<GameMode>,
+    mut status: ResMut<LobbyStatus>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut commands: Commands,
+) {
+    let connection = &mut client.connection;
+    let messages = match connection
+        .flush()
+        .and_then(|()| connection.receive::<ServerMessage>())
+    {
+        Ok(messages) => messages,
+        Err(e) => {
+            status.0 = format!("Lost the connection to the server: {}", e);
+            commands.remove_resource::<OnlineClient>();
+            return;
+        }
+    };
+
+    for message in messages {
+        match message {
+            ServerMessage::Start { player, seed } => {
+                *mode = GameMode::Versus;
+                commands.insert_resource(OnlineGame {
+                    local_player: player,
+                    seed,
+                });
+                next_state.set(GameState::Playing);
+                return;
+            }
+            ServerMessage::Rejected(reason) => {
+                status.0 = format!("The server turned us away: {}", reason);
+                commands.remove_resource::<OnlineClient>();
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sends a snapshot of the local board whenever it changes, along with garbage for the opponent and a loss if the
+/// local player topped out.
+#[allow(clippy::type_complexity)]
+pub fn send_online_updates(
+    mut client: ResMut<OnlineClient>,
+    online: Res<OnlineGame>,
+    board_query: Query<(&Player, &GameStats, &GarbageRise), (With<Board>, Without<Remote>)>,
+    grid_query: Query<&Grid>,
+    falling_piece_query: Query<&FallingPiece>,
+    mut outgoing_garbage: EventReader<OutgoingGarbage>,
+    mut game_over: EventReader<GameOver>,
+) {
+    let client = &mut *client;
+    for (player, stats, garbage) in board_query.iter() {
+        let snapshot = board_snapshot(
+            *player,
+            stats,
+            garbage.pending_rows(),
+            &grid_query,
+            &falling_piece_query,
+        );
+        let changed = client
+            .last_snapshot
+            .as_ref()
+            .is_none_or(|last| snapshot.looks_different(last));
+        if changed {
+            client
+                .connection
+                .send(&ClientMessage::Board(snapshot.clone()));
+            client.last_snapshot = Some(snapshot);
+        }
+    }
+
+    for event in outgoing_garbage.read() {
+        client.connection.send(&ClientMessage::Garbage(event.rows));
+    }
+
+    for event in game_over.read() {
+        if event.player == online.local_player {
+            client.connection.send(&ClientMessage::Lost);
+        }
+    }
+}
+
+/// Applies whatever the server relayed from the opponent. Losing the server ends the game.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn receive_online_messages(
+    mut client: ResMut<OnlineClient>,
+    online: Res<OnlineGame>,
+    mut board_query: Query<
+        (
+            &Player,
+            &mut GameStats,
+            Option<&mut GarbageRise>,
+            Option<&mut Remote>,
+        ),
+        With<Board>,
+    >,
+    mut grid_query: Query<&mut Grid>,
+    snapshot_piece_query: Query<(Entity, &Player), With<SnapshotPiece>>,
+    scene_assets: Res<SceneAssets>,
+    mut commands: Commands,
+    mut game_over: EventWriter<GameOver>,
+) {
+    let opponent = online.local_player.opponent();
+    let connection = &mut client.connection;
+    let messages = match connection
+        .flush()
+        .and_then(|()| connection.receive::<ServerMessage>())
+    {
+        Ok(messages) => messages,
+        Err(e) => {
+            warn!("Lost the connection to the server: {}", e);
+            game_over.send(GameOver {
+                player: opponent,
+                reason: GameOverReason::Disconnected,
+            });
+            return;
+        }
+    };
+
+    // Only the latest snapshot is worth drawing.
+    let mut latest_snapshot = None;
+    for message in messages {
+        match message {
+            ServerMessage::OpponentBoard(snapshot) => latest_snapshot = Some(snapshot),
+            ServerMessage::Garbage(rows) => {
+                for (_, _, garbage, _) in board_query
+                    .iter_mut()
+                    .filter(|(p, ..)| **p == online.local_player)
+                {
+                    if let Some(mut garbage) = garbage {
+                        garbage.add_rows(rows);
+                    }
+                }
+            }
+            ServerMessage::End { winner } => {
+                game_over.send(GameOver {
+                    player: winner.opponent(),
+                    reason: GameOverReason::ToppedOut,
+                });
+            }
+            ServerMessage::OpponentLeft => {
+                game_over.send(GameOver {
+                    player: opponent,
+                    reason: GameOverReason::Disconnected,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(snapshot) = latest_snapshot {
+        show_board_snapshot(
+            opponent,
+            &snapshot,
+            &mut grid_query,
+            &snapshot_piece_query,
+            &scene_assets,
+            &mut commands,
+        );
+        for (_, mut stats, _, remote) in board_query.iter_mut().filter(|(p, ..)| **p == opponent) {
+            if let Some(mut remote) = remote {
+                remote.update(&snapshot, &mut stats);
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{data_dir, grid_transform, Config, GridSide, Player};
+use bevy::{
+    input::{
+        gamepad::{Gamepad, GamepadAxis, GamepadButton},
+        mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
+    },
+    prelude::*,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+const CAMERA_FILE_NAME: &str = "camera.ron";
+/// Radians per pixel of mouse movement.
+const ORBIT_MOUSE_SPEED: f32 = 0.005;
+/// Radians per second with a stick all the way over.
+const ORBIT_STICK_SPEED: f32 = 2.0;
+/// Fraction of the distance per pixel of mouse movement, so panning feels the same at any zoom.
+const PAN_MOUSE_SPEED: f32 = 0.002;
+const PAN_STICK_SPEED: f32 = 1.0;
+/// Fraction of the distance per line scrolled.
+const ZOOM_SCROLL_SPEED: f32 = 0.1;
+const ZOOM_BUTTON_SPEED: f32 = 1.0;
+const MIN_DISTANCE: f32 = 5.0;
+const MAX_DISTANCE: f32 = 100.0;
+/// Keeps the camera from flipping over at the poles.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+/// Sticks rest a little off center.
+const STICK_DEAD_ZONE: f32 = 0.15;
+
+/// Where the players' cameras look from during a game, relative to each board. Every player's camera moves together.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CameraPose {
+    pub focus: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+impl CameraPose {
+    pub fn looking_at(position: Vec3, focus: Vec3) -> Self {
+        let transform = Transform::from_translation(position).looking_at(focus, Vec3::Y);
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+
+        Self {
+            focus,
+            yaw,
+            pitch,
+            distance: position.distance(focus),
+        }
+    }
+
+    /// `camera_position` and `camera_target` from the `Config`.
+    pub fn from_config(config: &Config) -> Self {
+        Self::looking_at(config.camera_position, config.camera_target)
+    }
+
+    pub fn preset(preset: CameraPreset, config: &Config) -> Self {
+        let default = Self::from_config(config);
+        match preset {
+            CameraPreset::Isometric => Self {
+                // The classic isometric angle, looking down the diagonal of a cube.
+                pitch: -(1.0 / 2.0f32.sqrt()).atan(),
+                yaw: default.yaw,
+                ..default
+            },
+            CameraPreset::LeftGrid => Self::facing_grid(GridSide::Left, config, default.distance),
+            CameraPreset::RightGrid => Self::facing_grid(GridSide::Right, config, default.distance),
+            CameraPreset::TopDown => Self {
+                pitch: -MAX_PITCH,
+                ..default
+            },
+        }
+    }
+
+    /// Straight on to the middle of a grid, from the side the default camera sees it from.
+    fn facing_grid(side: GridSide, config: &Config, distance: f32) -> Self {
+        let transform = grid_transform(side, config);
+        let [width, height] = config.grid_size;
+        let center = transform.transform_point(Vec3::new(width as f32, height as f32, 0.0) / 2.0);
+        let mut normal = transform.rotation * Vec3::Z;
+        if normal.dot(config.camera_position - center) < 0.0 {
+            normal = -normal;
+        }
+
+        Self::looking_at(center + normal * distance, center)
+    }
+
+    /// The preset or the configured camera, if its key in `Config::camera_keys` was just pressed.
+    pub fn from_keys(keyboard: &ButtonInput<KeyCode>, config: &Config) -> Option<Self> {
+        let keys = &config.camera_keys;
+        if keyboard.just_pressed(keys.reset) {
+            return Some(Self::from_config(config));
+        }
+        [
+            (keys.isometric, CameraPreset::Isometric),
+            (keys.left_grid, CameraPreset::LeftGrid),
+            (keys.right_grid, CameraPreset::RightGrid),
+            (keys.top_down, CameraPreset::TopDown),
+        ]
+        .iter()
+        .find(|(key, _)| keyboard.just_pressed(*key))
+        .map(|(_, preset)| Self::preset(*preset, config))
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0)
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.focus + self.rotation() * Vec3::Z * self.distance
+    }
+
+    /// Turns the camera around its focus. It stops just short of looking straight up or down, so it never flips over.
+    pub fn orbit(&mut self, delta: Vec2) {
+        self.yaw += delta.x;
+        self.pitch = (self.pitch + delta.y).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Turns the camera where it is, so the focus moves instead.
+    pub fn look_around(&mut self, delta: Vec2) {
+        let position = self.position();
+        self.orbit(delta);
+        self.focus = position - self.rotation() * Vec3::Z * self.distance;
+    }
+
+    /// Moves every player's camera to this pose, relative to their own board.
+    pub fn place_cameras(
+        &self,
+        camera_query: &mut Query<(&mut Transform, &Player), With<Camera3d>>,
+    ) {
+        let position = self.position();
+        let rotation = self.rotation();
+        for (mut tfm, player) in camera_query.iter_mut() {
+            tfm.translation = player.board_offset() + position;
+            tfm.rotation = rotation;
+        }
+    }
+
+    fn read_file(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        ron::de::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn write_file(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        std::fs::write(path, contents)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CameraPreset {
+    Isometric,
+    LeftGrid,
+    RightGrid,
+    TopDown,
+}
+
+/// The camera that the players move around during a game. The last pose is saved to the data directory whenever the
+/// camera comes to rest, and picked up again next time.
+#[derive(Resource)]
+pub struct OrbitCamera {
+    pose: CameraPose,
+    path: Option<PathBuf>,
+    unsaved: bool,
+}
+
+impl OrbitCamera {
+    pub fn pose(&self) -> &CameraPose {
+        &self.pose
+    }
+
+    pub fn set_pose(&mut self, pose: CameraPose) {
+        if self.pose != pose {
+            self.pose = pose;
+            self.unsaved = true;
+        }
+    }
+
+    fn save(&mut self) {
+        self.unsaved = false;
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = self.pose.write_file(path) {
+            warn!("Failed to save the camera to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Falls back to the camera from the `Config` if there's no saved pose or it can't be read.
+pub fn load_orbit_camera(config: Res<Config>, mut commands: Commands) {
+    let path = data_dir().map(|dir| dir.join(CAMERA_FILE_NAME));
+    let pose = match &path {
+        Some(path) => CameraPose::read_file(path).unwrap_or_else(|e| {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to read the camera from {}: {}", path.display(), e);
+            }
+            CameraPose::from_config(&config)
+        }),
+        None => CameraPose::from_config(&config),
+    };
+
+    commands.insert_resource(OrbitCamera {
+        pose,
+        path,
+        unsaved: false,
+    });
+}
+
+/// Dragging with the right mouse button or pushing the right stick orbits, dragging with the middle mouse button or
+/// pushing the left stick pans, and scrolling or pressing the sticks in zooms. `Config::camera_keys` jump to presets or
+/// back to the configured camera.
+#[allow(clippy::too_many_arguments)]
+pub fn move_orbit_camera(
+    config: Res<Config>,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    gamepad_query: Query<&Gamepad>,
+    mut orbit_camera: ResMut<OrbitCamera>,
+    mut camera_query: Query<(&mut Transform, &Player), With<Camera3d>>,
+) {
+    let mut pose = CameraPose::from_keys(&keyboard, &config).unwrap_or(orbit_camera.pose);
+
+    let delta_secs = time.delta_secs();
+    let mut orbit = Vec2::ZERO;
+    let mut pan = Vec2::ZERO;
+    let mut zoom = 0.0;
+    if mouse_buttons.pressed(MouseButton::Right) {
+        orbit -= mouse_motion.delta * ORBIT_MOUSE_SPEED;
+    }
+    if mouse_buttons.pressed(MouseButton::Middle) {
+        pan += mouse_motion.delta * Vec2::new(-1.0, 1.0) * PAN_MOUSE_SPEED;
+    }
+    zoom -= mouse_scroll.delta.y * ZOOM_SCROLL_SPEED;
+    for gamepad in gamepad_query.iter() {
+        let stick = |x, y| {
+            let v = Vec2::new(
+                gamepad.get(x).unwrap_or_default(),
+                gamepad.get(y).unwrap_or_default(),
+            );
+            if v.length() > STICK_DEAD_ZONE {
+                v
+            } else {
+                Vec2::ZERO
+            }
+        };
+        let right_stick = stick(GamepadAxis::RightStickX, GamepadAxis::RightStickY);
+        orbit += right_stick * Vec2::new(-1.0, 1.0) * ORBIT_STICK_SPEED * delta_secs;
+        pan +=
+            stick(GamepadAxis::LeftStickX, GamepadAxis::LeftStickY) * PAN_STICK_SPEED * delta_secs;
+        if gamepad.pressed(GamepadButton::RightThumb) {
+            zoom -= ZOOM_BUTTON_SPEED * delta_secs;
+        }
+        if gamepad.pressed(GamepadButton::LeftThumb) {
+            zoom += ZOOM_BUTTON_SPEED * delta_secs;
+        }
+    }
+
+    pose.orbit(orbit);
+    let rotation = pose.rotation();
+    pose.focus += (rotation * Vec3::X * pan.x + rotation * Vec3::Y * pan.y) * pose.distance;
+    pose.distance = (pose.distance * (1.0 + zoom)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+
+    let moving = pose != orbit_camera.pose;
+    orbit_camera.set_pose(pose);
+    if !moving && orbit_camera.unsaved {
+        orbit_camera.save();
+    }
+
+    pose.place_cameras(&mut camera_query);
+}
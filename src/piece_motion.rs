@@ -0,0 +1,91 @@
+use crate::Config;
+use bevy::prelude::*;
+
+/// Where the falling piece is drawn, easing toward where it logically is. Moves take effect on the `FallingPiece` right
+/// away, so this only changes how they look.
+#[derive(Component)]
+pub struct PieceMotion {
+    target: Transform,
+    translation_from: Vec3,
+    /// From 0 when the piece starts moving toward the target's translation, to 1 when it's there.
+    translation_progress: f32,
+    rotation_from: Quat,
+    rotation_progress: f32,
+}
+
+impl PieceMotion {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            target: transform,
+            translation_from: transform.translation,
+            translation_progress: 1.0,
+            rotation_from: transform.rotation,
+            rotation_progress: 1.0,
+        }
+    }
+
+    /// Where the piece is logically, which is where it's drawn once it stops moving.
+    pub fn target(&self) -> &Transform {
+        &self.target
+    }
+
+    /// Starts from wherever the piece is drawn now, so moves made in quick succession don't jump.
+    pub fn translate(&mut self, offset: Vec3) {
+        self.translation_from = self.translation();
+        self.translation_progress = 0.0;
+        self.target.translation += offset;
+    }
+
+    pub fn rotate(&mut self, rotation: Quat) {
+        self.rotation_from = self.rotation();
+        self.rotation_progress = 0.0;
+        self.target.rotation = rotation * self.target.rotation;
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.translation_from
+            .lerp(self.target.translation, ease_out(self.translation_progress))
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.rotation_from
+            .slerp(self.target.rotation, ease_out(self.rotation_progress))
+    }
+}
+
+/// Fast at first and slow at the end, so the piece is most of the way there as soon as it moves.
+fn ease_out(t: f32) -> f32 {
+    1.0 - (1.0 - t) * (1.0 - t)
+}
+
+fn advance(progress: f32, delta_secs: f32, duration_secs: f32) -> f32 {
+    if duration_secs <= 0.0 {
+        return 1.0;
+    }
+
+    (progress + delta_secs / duration_secs).min(1.0)
+}
+
+/// Moves the falling pieces' transforms along toward where the pieces are, taking `Config::piece_move_secs` for moves
+/// and drops and `Config::piece_rotate_secs` for rotations.
+pub fn animate_piece_motion(
+    time: Res<Time>,
+    config: Res<Config>,
+    mut piece_query: Query<(&mut PieceMotion, &mut Transform)>,
+) {
+    let delta_secs = time.delta_secs();
+    for (mut motion, mut tfm) in piece_query.iter_mut() {
+        motion.translation_progress = advance(
+            motion.translation_progress,
+            delta_secs,
+            config.piece_move_secs,
+        );
+        motion.rotation_progress = advance(
+            motion.rotation_progress,
+            delta_secs,
+            config.piece_rotate_secs,
+        );
+        tfm.translation = motion.translation();
+        tfm.rotation = motion.rotation();
+    }
+}
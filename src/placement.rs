@@ -0,0 +1,644 @@
+use crate::{move_accepted_in_grid, FallingPiece, FallingPieceAction, Grid, GridSide, Rotation};
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// How far a cube can be from the center of its piece, in any orientation.
+const MAX_CUBE_OFFSET: i32 = 2;
+
+/// A falling piece and where it has locked so far, without any entities. Actions apply to it the same way
+/// `update_falling_piece` applies them to the real piece, so it can be used to look ahead.
+#[derive(Clone, Copy, Debug)]
+pub struct PieceState {
+    pub piece: FallingPiece,
+    /// Where the piece locked in each grid it has stopped falling in, indexed like the grids passed to `apply`.
+    pub locked: [Option<FallingPiece>; 2],
+}
+
+impl PieceState {
+    pub fn new(piece: FallingPiece) -> Self {
+        Self {
+            piece,
+            locked: [None; 2],
+        }
+    }
+
+    /// Whether the piece has locked in every grid.
+    pub fn is_placed(&self) -> bool {
+        self.locked.iter().all(Option::is_some)
+    }
+
+    /// Returns false if the action was rejected and nothing changed.
+    pub fn apply(&mut self, action: &FallingPieceAction, grids: [&Grid; 2]) -> bool {
+        match action {
+            FallingPieceAction::Spawn => false,
+            FallingPieceAction::Drop => self.drop(grids),
+            FallingPieceAction::FastDrop => {
+                let mut changed = false;
+                while !self.is_placed() {
+                    changed |= self.drop(grids);
+                }
+
+                changed
+            }
+            FallingPieceAction::Translate(translation) => {
+                let mut new_piece = self.piece;
+                new_piece.translate(*translation);
+                self.try_move(new_piece, grids)
+            }
+            FallingPieceAction::Rotate(rotation) => {
+                let mut new_piece = self.piece;
+                new_piece.rotate(rotation.matrix);
+                self.try_move(new_piece, grids)
+            }
+        }
+    }
+
+    /// The piece's cells in a grid: where it locked, or where it is if it's still falling there.
+    pub fn cells(&self, grid: &Grid, index: usize) -> [IVec2; 4] {
+        grid.project_piece(&self.locked[index].unwrap_or(self.piece))
+    }
+
+    fn try_move(&mut self, new_piece: FallingPiece, grids: [&Grid; 2]) -> bool {
+        let accepted = (0..2)
+            .filter(|i| self.locked[*i].is_none())
+            .all(|i| move_accepted_in_grid(&new_piece, grids[i]));
+        if self.is_placed() || !accepted {
+            return false;
+        }
+        self.piece = new_piece;
+
+        true
+    }
+
+    /// Grids that the piece can't fall any further in lock it where it is, and it keeps falling in the others.
+    fn drop(&mut self, grids: [&Grid; 2]) -> bool {
+        if self.is_placed() {
+            return false;
+        }
+        let mut new_piece = self.piece;
+        new_piece.translate_n_rows(-1);
+
+        let mut accepted = true;
+        for (i, grid) in grids.iter().enumerate() {
+            if self.locked[i].is_none() && !move_accepted_in_grid(&new_piece, grid) {
+                self.locked[i] = Some(self.piece);
+                accepted = false;
+            }
+        }
+        if accepted {
+            self.piece = new_piece;
+        }
+
+        true
+    }
+}
+
+/// Every distinct way a piece can lock in both grids, found with a breadth-first search over rotations, moves and
+/// drops. Orientations that cast the same shadows count as the same placement.
+///
+/// Once the piece has locked in one grid, it can still go almost anywhere in the other one, so there can be tens of
+/// thousands of placements. Each grid's outcomes are only stored once, and paths are only worked out when asked for.
+///
+/// Gravity isn't taken into account, so a path is only guaranteed to work if it's played faster than the piece falls.
+pub struct ReachablePlacements {
+    actions: [FallingPieceAction; 9],
+    tree: SearchTree,
+    single_grid_graphs: [SingleGridGraph; 2],
+    outcomes: [OutcomeTable; 2],
+    placements: Vec<ReachablePlacement>,
+}
+
+/// One way the piece can lock in both grids. `left` and `right` index into `ReachablePlacements::outcomes`.
+#[derive(Clone, Copy, Debug)]
+pub struct ReachablePlacement {
+    pub left: usize,
+    pub right: usize,
+    route: Route,
+}
+
+/// How to get to a placement, from the search tree and maybe a single grid graph. Actions are indices into
+/// `ReachablePlacements::actions`.
+#[derive(Clone, Copy, Debug)]
+enum Route {
+    /// The piece locked in both grids at once, with an action taken from a node of the search tree.
+    Together { node: usize, action: usize },
+    /// The piece locked in one grid with an action taken from a node of the search tree, then went from `start` to
+    /// `last` in the other grid's graph, where `last_action` locked it.
+    OneAtATime {
+        node: usize,
+        action: usize,
+        active: usize,
+        start: usize,
+        last: usize,
+        last_action: usize,
+    },
+}
+
+impl ReachablePlacements {
+    pub fn search(piece: &FallingPiece, left: &Grid, right: &Grid) -> Self {
+        let grids = [left, right];
+        let actions = all_actions();
+        let start = PieceState::new(*piece);
+        let mut tree = SearchTree::new(start);
+        let mut single_grid_graphs =
+            [0, 1].map(|active| SingleGridGraph::new(active, open_air_row(&[grids[active]])));
+        let mut outcomes = [OutcomeTable::default(), OutcomeTable::default()];
+        let mut placements = Vec::new();
+
+        let mut found = HashSet::new();
+        // What happens in the grid that's still active doesn't depend on where the piece locked in the other one, so
+        // each start is only explored once.
+        let mut continuations: HashMap<(usize, usize), Vec<Continuation>> = HashMap::new();
+        let open_air = open_air_row(&grids);
+        let fits = grids.iter().all(|g| move_accepted_in_grid(piece, g));
+        let mut node = 0;
+        while fits && node < tree.nodes.len() {
+            let state = tree.nodes[node].state;
+            for (action, next) in state.successors(grids, &actions, None, open_air) {
+                match next.locked {
+                    [Some(_), Some(_)] => {
+                        let left = outcomes[0].intern(next.cells(left, 0));
+                        let right = outcomes[1].intern(next.cells(right, 1));
+                        if found.insert((left, right)) {
+                            placements.push(ReachablePlacement {
+                                left,
+                                right,
+                                route: Route::Together { node, action },
+                            });
+                        }
+                    }
+                    [None, None] => tree.push(next, node, action),
+                    [Some(_), None] | [None, Some(_)] => {
+                        let active = if next.locked[0].is_none() { 0 } else { 1 };
+                        let locked_outcome =
+                            outcomes[1 - active].intern(next.cells(grids[1 - active], 1 - active));
+                        let graph = &mut single_grid_graphs[active];
+                        let start = graph.node(next);
+                        let active_outcomes = &mut outcomes[active];
+                        let reached = continuations.entry((active, start)).or_insert_with(|| {
+                            graph.explore(start, grids, &actions, active_outcomes)
+                        });
+                        for (outcome, last, last_action) in reached.iter().cloned() {
+                            let mut ids = [locked_outcome; 2];
+                            ids[active] = outcome;
+                            if found.insert((ids[0], ids[1])) {
+                                placements.push(ReachablePlacement {
+                                    left: ids[0],
+                                    right: ids[1],
+                                    route: Route::OneAtATime {
+                                        node,
+                                        action,
+                                        active,
+                                        start,
+                                        last,
+                                        last_action,
+                                    },
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+            node += 1;
+        }
+
+        Self {
+            actions,
+            tree,
+            single_grid_graphs,
+            outcomes,
+            placements,
+        }
+    }
+
+    pub fn placements(&self) -> &[ReachablePlacement] {
+        &self.placements
+    }
+
+    /// The distinct places the piece can lock in one grid. Each is sorted, and cubes that are in front of each other
+    /// only count once.
+    pub fn outcomes(&self, side: GridSide) -> &[Vec<IVec2>] {
+        &self.outcomes[side_index(side)].cells
+    }
+
+    pub fn cells(&self, placement: &ReachablePlacement, side: GridSide) -> &[IVec2] {
+        let outcome = match side {
+            GridSide::Left => placement.left,
+            GridSide::Right => placement.right,
+        };
+
+        &self.outcomes(side)[outcome]
+    }
+
+    /// The actions that get the piece from where it started to the placement, with the shortest path the search
+    /// found. Rows are dropped one at a time, except at the end, where a fast drop finishes the job.
+    pub fn path(&self, placement: &ReachablePlacement) -> Vec<FallingPieceAction> {
+        let action_indices = match placement.route {
+            Route::Together { node, action } => {
+                let mut path = self.tree.path(node);
+                path.push(action);
+                path
+            }
+            Route::OneAtATime {
+                node,
+                action,
+                active,
+                start,
+                last,
+                last_action,
+            } => {
+                let mut path = self.tree.path(node);
+                path.push(action);
+                path.extend(self.single_grid_graphs[active].path(start, last));
+                path.push(last_action);
+                path
+            }
+        };
+        let mut path: Vec<FallingPieceAction> = action_indices
+            .into_iter()
+            .map(|i| self.actions[i].clone())
+            .collect();
+
+        // Drops at the end of a path are the same as one fast drop.
+        while let Some(FallingPieceAction::Drop) = path.last() {
+            path.pop();
+        }
+        path.push(FallingPieceAction::FastDrop);
+
+        path
+    }
+}
+
+impl PieceState {
+    /// The states one action away, with the index of the action. When the piece is only falling in the `only_active`
+    /// grid, moves along the axis that only the other grid sees are skipped, or the piece could wander off forever.
+    ///
+    /// Above `open_air`, the piece only drops. Anything it could do up there, it can do just as well once it's down at
+    /// `open_air`, where it's further from the top of the grids and still can't bump into anything else.
+    fn successors<'a>(
+        self,
+        grids: [&'a Grid; 2],
+        actions: &'a [FallingPieceAction],
+        only_active: Option<usize>,
+        open_air: i32,
+    ) -> impl Iterator<Item = (usize, PieceState)> + 'a {
+        let hidden_axis = match only_active {
+            Some(0) => IVec3::Z,
+            Some(_) => IVec3::X,
+            None => IVec3::ZERO,
+        };
+        let only_drop = self.piece.cell_positions()[0].y > open_air;
+        actions.iter().enumerate().filter_map(move |(i, action)| {
+            if only_drop && !matches!(action, FallingPieceAction::Drop) {
+                return None;
+            }
+            if let FallingPieceAction::Translate(translation) = action {
+                if translation.dot(hidden_axis) != 0 {
+                    return None;
+                }
+            }
+            let mut next = self;
+            next.apply(action, grids).then_some((i, next))
+        })
+    }
+}
+
+/// Every state the search has reached while the piece is falling in both grids, each with the node and action it was
+/// reached from.
+struct SearchTree {
+    nodes: Vec<SearchNode>,
+    seen: HashSet<[[i32; 3]; 4]>,
+}
+
+struct SearchNode {
+    state: PieceState,
+    parent: Option<(usize, usize)>,
+}
+
+impl SearchTree {
+    fn new(start: PieceState) -> Self {
+        Self {
+            nodes: vec![SearchNode {
+                state: start,
+                parent: None,
+            }],
+            seen: HashSet::from([shape_key(&start.piece)]),
+        }
+    }
+
+    /// Does nothing if the piece has already been in the same place.
+    fn push(&mut self, state: PieceState, parent: usize, action: usize) {
+        if self.seen.insert(shape_key(&state.piece)) {
+            self.nodes.push(SearchNode {
+                state,
+                parent: Some((parent, action)),
+            });
+        }
+    }
+
+    /// The actions that lead from the start to `node`.
+    fn path(&self, mut node: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        while let Some((parent, action)) = self.nodes[node].parent {
+            path.push(action);
+            node = parent;
+        }
+        path.reverse();
+
+        path
+    }
+}
+
+/// Every state the piece has been in while falling in only one grid, with the axis that only the other grid sees left
+/// out, and where each action takes it. Edges are worked out the first time a state is explored.
+struct SingleGridGraph {
+    active: usize,
+    open_air: i32,
+    states: Vec<PieceState>,
+    index: HashMap<[[i32; 3]; 4], usize>,
+    edges: Vec<Option<[Edge; 9]>>,
+}
+
+/// An outcome reached in a single grid graph, with the node and action that reached it.
+type Continuation = (usize, usize, usize);
+
+#[derive(Clone, Copy, Debug)]
+enum Edge {
+    Rejected,
+    Moved(usize),
+    /// The piece locked, with an index into the active grid's outcomes.
+    Placed(usize),
+}
+
+impl SingleGridGraph {
+    fn new(active: usize, open_air: i32) -> Self {
+        Self {
+            active,
+            open_air,
+            states: Vec::new(),
+            index: HashMap::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// The node for a state, added to the graph if it's new. Where the piece is along the hidden axis doesn't matter
+    /// any more, so it's moved to 0 first.
+    fn node(&mut self, mut state: PieceState) -> usize {
+        let hidden_axis = if self.active == 0 { IVec3::Z } else { IVec3::X };
+        let center = state.piece.cell_positions()[0];
+        state
+            .piece
+            .translate(-center.dot(hidden_axis) * hidden_axis);
+
+        let key = shape_key(&state.piece);
+        if let Some(node) = self.index.get(&key) {
+            return *node;
+        }
+        self.states.push(state);
+        self.edges.push(None);
+        self.index.insert(key, self.states.len() - 1);
+
+        self.states.len() - 1
+    }
+
+    fn edges(
+        &mut self,
+        node: usize,
+        grids: [&Grid; 2],
+        actions: &[FallingPieceAction],
+        outcomes: &mut OutcomeTable,
+    ) -> [Edge; 9] {
+        if let Some(edges) = self.edges[node] {
+            return edges;
+        }
+
+        let mut edges = [Edge::Rejected; 9];
+        let state = self.states[node];
+        for (action, next) in state.successors(grids, actions, Some(self.active), self.open_air) {
+            edges[action] = if next.is_placed() {
+                Edge::Placed(outcomes.intern(next.cells(grids[self.active], self.active)))
+            } else {
+                Edge::Moved(self.node(next))
+            };
+        }
+        self.edges[node] = Some(edges);
+
+        edges
+    }
+
+    /// Every outcome reachable from `start`, each with the node and action that first reached it.
+    fn explore(
+        &mut self,
+        start: usize,
+        grids: [&Grid; 2],
+        actions: &[FallingPieceAction],
+        outcomes: &mut OutcomeTable,
+    ) -> Vec<Continuation> {
+        let mut reached = Vec::new();
+        let mut reached_outcomes = HashSet::new();
+        let mut visited = vec![false; self.states.len()];
+        visited[start] = true;
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            for (action, edge) in self
+                .edges(node, grids, actions, outcomes)
+                .iter()
+                .enumerate()
+            {
+                match *edge {
+                    Edge::Rejected => {}
+                    Edge::Moved(next) => {
+                        if next >= visited.len() {
+                            visited.resize(self.states.len(), false);
+                        }
+                        if !visited[next] {
+                            visited[next] = true;
+                            queue.push_back(next);
+                        }
+                    }
+                    Edge::Placed(outcome) => {
+                        if reached_outcomes.insert(outcome) {
+                            reached.push((outcome, node, action));
+                        }
+                    }
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// The actions that lead from `start` to `end`, which `explore` must have found.
+    fn path(&self, start: usize, end: usize) -> Vec<usize> {
+        let mut parents = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            if node == end {
+                break;
+            }
+            let edges = self.edges[node].unwrap_or([Edge::Rejected; 9]);
+            for (action, edge) in edges.iter().enumerate() {
+                if let Edge::Moved(next) = *edge {
+                    if next != start && !parents.contains_key(&next) {
+                        parents.insert(next, (node, action));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        let mut path = Vec::new();
+        let mut node = end;
+        while let Some((parent, action)) = parents.get(&node) {
+            path.push(*action);
+            node = *parent;
+        }
+        path.reverse();
+
+        path
+    }
+}
+
+/// The distinct places the piece has locked in one grid, each stored once.
+#[derive(Default)]
+struct OutcomeTable {
+    cells: Vec<Vec<IVec2>>,
+    ids: HashMap<ShadowKey, usize>,
+}
+
+impl OutcomeTable {
+    fn intern(&mut self, cells: [IVec2; 4]) -> usize {
+        let key = shadow_key(cells);
+        if let Some(id) = self.ids.get(&key) {
+            return *id;
+        }
+        let mut cells: Vec<IVec2> = key.iter().map(|p| IVec2::from_array(*p)).collect();
+        cells.dedup();
+        self.cells.push(cells);
+        self.ids.insert(key, self.cells.len() - 1);
+
+        self.cells.len() - 1
+    }
+}
+
+/// The piece's cells sorted, so that a piece's cells in a grid are the same whichever order its cubes are in.
+type ShadowKey = [[i32; 2]; 4];
+
+fn shadow_key(cells: [IVec2; 4]) -> ShadowKey {
+    let mut key = cells.map(|p| p.to_array());
+    key.sort_unstable();
+
+    key
+}
+
+/// Where the piece's center and other cubes are. Orientations that put the cubes in the same places behave the same
+/// from then on, since rotations are around the center.
+fn shape_key(piece: &FallingPiece) -> [[i32; 3]; 4] {
+    let mut key = piece.cell_positions().map(|p| p.to_array());
+    key[1..].sort_unstable();
+
+    key
+}
+
+/// The lowest row that the piece can't bump into anything at, with its center there.
+fn open_air_row(grids: &[&Grid]) -> i32 {
+    grids.iter().map(|g| g.stack_height()).max().unwrap_or(0) + MAX_CUBE_OFFSET
+}
+
+fn side_index(side: GridSide) -> usize {
+    match side {
+        GridSide::Left => 0,
+        GridSide::Right => 1,
+    }
+}
+
+fn all_actions() -> [FallingPieceAction; 9] {
+    [
+        FallingPieceAction::Drop,
+        FallingPieceAction::Translate(IVec3::X),
+        FallingPieceAction::Translate(IVec3::NEG_X),
+        FallingPieceAction::Translate(IVec3::Z),
+        FallingPieceAction::Translate(IVec3::NEG_Z),
+        FallingPieceAction::Rotate(Rotation::rotate_x_pos_90()),
+        FallingPieceAction::Rotate(Rotation::rotate_x_neg_90()),
+        FallingPieceAction::Rotate(Rotation::rotate_z_pos_90()),
+        FallingPieceAction::Rotate(Rotation::rotate_z_neg_90()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CellValue, PieceType, Player, ALL_PIECE_TYPES};
+
+    const GRID_SIZE: [usize; 2] = [6, 12];
+
+    fn grids() -> [Grid; 2] {
+        [GridSide::Left, GridSide::Right].map(|side| Grid::headless(side, Player::One, GRID_SIZE))
+    }
+
+    fn sorted_cells(cells: [IVec2; 4]) -> Vec<IVec2> {
+        let mut cells = cells.to_vec();
+        cells.sort_unstable_by_key(|p| p.to_array());
+        cells.dedup();
+
+        cells
+    }
+
+    #[test]
+    fn stick_has_every_placement_on_empty_grids() {
+        let [left, right] = grids();
+        let piece = FallingPiece::at_spawn(PieceType::Stick, Player::One, GRID_SIZE);
+        let reachable = ReachablePlacements::search(&piece, &left, &right);
+
+        // Lying along the grid, across it, or standing up, anywhere it fits on the floor.
+        let width = GRID_SIZE[0];
+        for side in [GridSide::Left, GridSide::Right] {
+            assert_eq!(reachable.outcomes(side).len(), (width - 3) + width + width);
+        }
+        // Lying down in one grid is across the other one, and standing up shows in both.
+        assert_eq!(
+            reachable.placements().len(),
+            2 * (width - 3) * width + width * width
+        );
+    }
+
+    #[test]
+    fn paths_lead_to_their_placements() {
+        let [mut left, right] = grids();
+        // A column in one grid makes the piece lock there first, sometimes.
+        left.fill_master(|p| {
+            if p.x == 0 && p.y < 3 {
+                CellValue::Garbage
+            } else {
+                CellValue::Empty
+            }
+        });
+
+        for piece_type in ALL_PIECE_TYPES.iter().cloned() {
+            let piece = FallingPiece::at_spawn(piece_type, Player::One, GRID_SIZE);
+            let reachable = ReachablePlacements::search(&piece, &left, &right);
+            assert!(!reachable.placements().is_empty());
+
+            for placement in reachable.placements() {
+                let mut state = PieceState::new(piece);
+                for action in reachable.path(placement) {
+                    assert!(
+                        state.apply(&action, [&left, &right]),
+                        "{:?} was rejected",
+                        action
+                    );
+                }
+                assert!(state.is_placed());
+                assert_eq!(
+                    sorted_cells(state.cells(&left, 0)),
+                    reachable.cells(placement, GridSide::Left)
+                );
+                assert_eq!(
+                    sorted_cells(state.cells(&right, 1)),
+                    reachable.cells(placement, GridSide::Right)
+                );
+            }
+        }
+    }
+}
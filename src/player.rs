@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+/// Boards are spaced far enough apart that each player's camera only ever sees its own board.
+const BOARD_SPACING: f32 = 1000.0;
+
+/// Which player an entity belongs to. Single player games only have `Player::One`.
+#[derive(
+    Clone,
+    Component,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+pub enum Player {
+    #[default]
+    One,
+    Two,
+}
+
+pub const ALL_PLAYERS: [Player; 2] = [Player::One, Player::Two];
+
+impl Player {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Player::One => "Player 1",
+            Player::Two => "Player 2",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn opponent(&self) -> Player {
+        match self {
+            Player::One => Player::Two,
+            Player::Two => Player::One,
+        }
+    }
+
+    /// Where this player's grids and pieces live in the world, relative to the single player layout.
+    pub fn board_offset(&self) -> Vec3 {
+        -Vec3::Y * BOARD_SPACING * self.index() as f32
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{CellValue, GameMode, GameStats, PieceType, Player};
+use bevy::math::IVec3;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Bumped whenever a message changes, so that mismatched clients are turned away instead of misunderstood.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+pub const DEFAULT_SERVER_ADDRESS: &str = "0.0.0.0:7878";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a closing connection waits for the other end to take whatever is still queued.
+const CLOSE_TIMEOUT: Duration = Duration::from_millis(250);
+/// Far more than the biggest board snapshot. A longer line means the other end is broken or hostile, so instead of
+/// buffering it, the connection is dropped.
+const MAX_LINE_LEN: usize = 1 << 20;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ClientMessage {
+    /// The first message on every connection. Players are only matched with others using the same grid size.
+    Hello { version: u32, grid_size: [usize; 2] },
+    /// Sent whenever the sender's board changes.
+    Board(BoardSnapshot),
+    /// Rows for the opponent, left over after cancelling the sender's own incoming garbage.
+    Garbage(u32),
+    /// The sender topped out.
+    Lost,
+    /// Sent instead of `Hello` by a local game that lets spectators watch it.
+    Broadcast { version: u32, grid_size: [usize; 2] },
+    /// Sent by a broadcasting game whenever it starts a new game.
+    Started { mode: GameMode },
+    /// Sent by a broadcasting game, since it can have more than one board.
+    PlayerBoard {
+        player: Player,
+        snapshot: BoardSnapshot,
+    },
+    /// Sent by a broadcasting game when its game ends.
+    Finished,
+    /// Sent instead of `Hello` by a spectator.
+    Spectate { version: u32 },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ServerMessage {
+    /// The connection is closed right after this.
+    Rejected(String),
+    /// An opponent was found. Both players get the same seed, so they get the same pieces and garbage holes.
+    Start {
+        player: Player,
+        seed: u64,
+    },
+    OpponentBoard(BoardSnapshot),
+    Garbage(u32),
+    /// The first player to top out loses. The connection is closed right after this.
+    End {
+        winner: Player,
+    },
+    /// The opponent disconnected in the middle of the game.
+    OpponentLeft,
+    /// Tells a spectator which game it's watching now. Every board of that game follows as a `PlayerBoard`.
+    Watching {
+        grid_size: [usize; 2],
+        mode: GameMode,
+    },
+    PlayerBoard {
+        player: Player,
+        snapshot: BoardSnapshot,
+    },
+    /// The watched game is over. Spectators move on to the next game that starts.
+    Finished,
+}
+
+/// What the opponent needs to draw a player's board. Each grid's cells are listed row by row from the bottom.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BoardSnapshot {
+    pub left: Vec<CellValue>,
+    pub right: Vec<CellValue>,
+    pub stats: GameStats,
+    pub incoming_garbage: u32,
+    pub piece: Option<PieceSnapshot>,
+}
+
+impl BoardSnapshot {
+    /// Whether anything a viewer can see is different. The elapsed time changes every frame, so it doesn't count.
+    pub fn looks_different(&self, other: &BoardSnapshot) -> bool {
+        self.left != other.left
+            || self.right != other.right
+            || self.piece != other.piece
+            || self.incoming_garbage != other.incoming_garbage
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PieceSnapshot {
+    pub piece_type: PieceType,
+    pub cell_positions: [IVec3; 4],
+}
+
+/// A non-blocking TCP connection that carries one RON message per line.
+///
+/// Sending only queues a message. Nothing is written until `flush`, so that errors are handled in one place.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            closed: false,
+        })
+    }
+
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no addresses found");
+        for socket_address in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+                Ok(stream) => return Self::new(stream),
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    pub fn send<T: Serialize>(&mut self, message: &T) {
+        let line = ron::ser::to_string(message).expect("Messages can always be serialized");
+        self.outgoing.extend_from_slice(line.as_bytes());
+        self.outgoing.push(b'\n');
+    }
+
+    /// Writes as much of the queue as the socket will take without blocking.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns every complete message that has arrived so far. Once the other end has closed the connection and every
+    /// message is taken, this returns an error.
+    pub fn receive<T: DeserializeOwned>(&mut self) -> io::Result<Vec<T>> {
+        let mut messages = Vec::new();
+        let mut buffer = [0; 4096];
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(n) => {
+                    self.incoming.extend_from_slice(&buffer[..n]);
+                    self.take_lines(&mut messages)?;
+                    // Whatever is left is the start of the next line.
+                    if self.incoming.len() > MAX_LINE_LEN {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("a message is longer than {} bytes", MAX_LINE_LEN),
+                        ));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if self.closed && messages.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Ok(messages)
+    }
+
+    fn take_lines<T: DeserializeOwned>(&mut self, messages: &mut Vec<T>) -> io::Result<()> {
+        while let Some(end) = self.incoming.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            let message = std::str::from_utf8(&line)
+                .map_err(|e| e.to_string())
+                .and_then(|line| ron::de::from_str(line).map_err(|e| e.to_string()))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            messages.push(message);
+        }
+
+        Ok(())
+    }
+}
+
+/// Gives the other end a moment to take the last messages, e.g. a `Lost` sent just before leaving the game.
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.outgoing.is_empty() {
+            return;
+        }
+        let _ = self.stream.set_nonblocking(false);
+        let _ = self.stream.set_write_timeout(Some(CLOSE_TIMEOUT));
+        let _ = self.stream.write_all(&self.outgoing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn overlong_line_drops_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut connection = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        let chunk = vec![b'x'; 64 * 1024];
+        let mut sent = 0;
+        let result = loop {
+            if sent <= MAX_LINE_LEN {
+                sender.write_all(&chunk).unwrap();
+                sent += chunk.len();
+            }
+            match connection.receive::<ClientMessage>() {
+                Ok(messages) => assert!(messages.is_empty()),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(result.kind(), io::ErrorKind::InvalidData);
+        assert!(connection.incoming.len() <= MAX_LINE_LEN + 4096);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    Board, CellValue, Config, GameMode, GameOver, GameOverReason, GameState, GameStats, GrabBag,
+    Grid, GridSide, PieceType, Player,
+};
+use bevy::prelude::*;
+
+const PUZZLE_DIR: &str = "assets/puzzles";
+
+/// Both grids need to clear at least `rows_per_grid` rows, using no more than `max_pieces` pieces.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct PuzzleGoal {
+    pub rows_per_grid: u32,
+    pub max_pieces: u32,
+}
+
+/// The file format of a puzzle.
+///
+/// The initial contents of each grid are given as rows of text from top to bottom, and they're stacked on the floor of
+/// the grid. A '.' is an empty cell, a 'G' is garbage, and any other cell is filled with the piece whose name starts
+/// with that letter, e.g. 'S' for `Stick`.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Puzzle {
+    pub name: String,
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    /// Handed out in order, starting over if they run out before `PuzzleGoal::max_pieces`.
+    pub pieces: Vec<PieceType>,
+    pub goal: PuzzleGoal,
+}
+
+impl Puzzle {
+    pub fn read_file(path: &std::path::Path) -> Result<Self, ron::Error> {
+        let reader = std::fs::File::open(path)?;
+
+        ron::de::from_reader(reader)
+    }
+
+    /// Checks that the puzzle fits in grids of `grid_size`.
+    pub fn validate(&self, grid_size: [usize; 2]) -> Result<(), String> {
+        if self.pieces.is_empty() {
+            return Err("a puzzle needs at least one piece".into());
+        }
+        for rows in [&self.left, &self.right] {
+            parse_rows(rows, grid_size)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn rows(&self, side: GridSide) -> &[String] {
+        match side {
+            GridSide::Left => &self.left,
+            GridSide::Right => &self.right,
+        }
+    }
+}
+
+/// Returns the filled cells of `rows`, indexed by `[y][x]` with `y = 0` at the bottom.
+fn parse_rows(rows: &[String], grid_size: [usize; 2]) -> Result<Vec<Vec<CellValue>>, String> {
+    let [width, height] = grid_size;
+    if rows.len() > height {
+        return Err(format!(
+            "{} rows don't fit in a grid of height {}",
+            rows.len(),
+            height
+        ));
+    }
+
+    rows.iter()
+        .rev()
+        .map(|row| {
+            if row.chars().count() != width {
+                return Err(format!(
+                    "row {:?} doesn't match the grid width {}",
+                    row, width
+                ));
+            }
+            if !row.contains('.') {
+                return Err(format!("row {:?} is already full", row));
+            }
+            row.chars()
+                .map(|c| cell_from_char(c).ok_or_else(|| format!("unknown cell {:?}", c)))
+                .collect()
+        })
+        .collect()
+}
+
+fn cell_from_char(c: char) -> Option<CellValue> {
+    let piece_type = match c {
+        '.' => return Some(CellValue::Empty),
+        'G' => return Some(CellValue::Garbage),
+        'S' => PieceType::Stick,
+        'B' => PieceType::Box,
+        'J' => PieceType::Jay,
+        'Z' => PieceType::Zee,
+        'T' => PieceType::Tee,
+        'C' => PieceType::Chi,
+        'R' => PieceType::Ral,
+        'L' => PieceType::Legs,
+        _ => return None,
+    };
+
+    Some(CellValue::Piece(piece_type))
+}
+
+/// All of the puzzles that fit the configured grid size, sorted by file name.
+#[derive(Default, Resource)]
+pub struct PuzzleLibrary {
+    puzzles: Vec<Puzzle>,
+}
+
+impl PuzzleLibrary {
+    pub fn puzzles(&self) -> &[Puzzle] {
+        &self.puzzles
+    }
+}
+
+/// An index into the `PuzzleLibrary` for the next puzzle game.
+#[derive(Default, Resource)]
+pub struct SelectedPuzzle(pub usize);
+
+/// The puzzle being played.
+#[derive(Resource)]
+pub struct ActivePuzzle(pub Puzzle);
+
+/// Broken puzzles are skipped so they don't take the rest of the library down with them.
+pub fn load_puzzle_library(config: Res<Config>, mut commands: Commands) {
+    let mut paths: Vec<std::path::PathBuf> = match std::fs::read_dir(PUZZLE_DIR) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "ron"))
+            .collect(),
+        Err(e) => {
+            warn!("Failed to read puzzles from {}: {}", PUZZLE_DIR, e);
+            Vec::new()
+        }
+    };
+    paths.sort();
+
+    let mut puzzles = Vec::new();
+    for path in paths {
+        let result = Puzzle::read_file(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|puzzle| puzzle.validate(config.grid_size).map(|()| puzzle));
+        match result {
+            Ok(puzzle) => puzzles.push(puzzle),
+            Err(e) => warn!("Skipping puzzle {}: {}", path.display(), e),
+        }
+    }
+
+    commands.insert_resource(PuzzleLibrary { puzzles });
+}
+
+/// Should run after `create_game` so it can replace the empty grids and the random grab bag. Without a puzzle to play,
+/// e.g. from `--mode puzzle` with an empty library, the game is dropped in favor of the puzzle list.
+#[allow(clippy::too_many_arguments)]
+pub fn set_up_puzzle(
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    library: Res<PuzzleLibrary>,
+    selected: Res<SelectedPuzzle>,
+    mut grid_query: Query<&mut Grid>,
+    mut board_query: Query<&mut GrabBag, With<Board>>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if *mode != GameMode::Puzzle {
+        return;
+    }
+    let Some(puzzle) = library.puzzles().get(selected.0) else {
+        warn!(
+            "There is no puzzle {} to play, so going back to the puzzle list",
+            selected.0
+        );
+        next_state.set(GameState::PuzzleSelect);
+        return;
+    };
+
+    for mut grid in grid_query.iter_mut() {
+        // Validated when the library was loaded.
+        let rows = parse_rows(puzzle.rows(grid.side()), config.grid_size).unwrap();
+        grid.fill_master(|p| {
+            rows.get(p.y as usize)
+                .map_or(CellValue::Empty, |row| row[p.x as usize])
+        });
+    }
+
+    for mut grab_bag in board_query.iter_mut() {
+        *grab_bag = GrabBag::from_sequence(puzzle.pieces.clone());
+    }
+    commands.insert_resource(ActivePuzzle(puzzle.clone()));
+}
+
+pub fn check_puzzle_goal(
+    puzzle: Res<ActivePuzzle>,
+    board_query: Query<(&Player, &GameStats), With<Board>>,
+    mut game_over: EventWriter<GameOver>,
+) {
+    let goal = &puzzle.0.goal;
+    for (player, stats) in board_query.iter() {
+        let solved = [GridSide::Left, GridSide::Right]
+            .iter()
+            .all(|side| stats.lines(*side) >= goal.rows_per_grid);
+        if solved {
+            game_over.send(GameOver {
+                player: *player,
+                reason: GameOverReason::Completed,
+            });
+        } else if stats.pieces_placed() >= goal.max_pieces {
+            game_over.send(GameOver {
+                player: *player,
+                reason: GameOverReason::OutOfPieces,
+            });
+        }
+    }
+}
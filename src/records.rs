@@ -0,0 +1,292 @@
+use crate::{
+    ActivePuzzle, Autoplay, Board, Config, GameMode, GameOverReason, GameStats, GrabBag,
+    PlacementHint, Player,
+};
+use bevy::prelude::*;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const RECORDS_FILE_NAME: &str = "records.ron";
+const DEFAULT_PROFILE_NAME: &str = "Player";
+
+/// A finished game.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct GameRecord {
+    pub profile: String,
+    pub mode: GameMode,
+    pub score: u64,
+    pub lines: u32,
+    pub level: u32,
+    pub duration_secs: f64,
+    pub seed: u64,
+    pub pieces_placed: u32,
+    /// Seconds since the Unix epoch.
+    pub finished_at: u64,
+    /// Whether the mode's goal was reached, as opposed to topping out.
+    #[serde(default)]
+    pub completed: bool,
+    /// See `GameMode::goal`.
+    #[serde(default)]
+    pub goal: Option<u32>,
+    /// See `GameStats::line_splits`.
+    #[serde(default)]
+    pub line_splits: Vec<f64>,
+    /// The name of the puzzle, in `GameMode::Puzzle`.
+    #[serde(default)]
+    pub puzzle: Option<String>,
+    /// Whether placement hints were shown during the game. Assisted games still count as played and solved, but
+    /// they're left out of high scores.
+    #[serde(default)]
+    pub assisted: bool,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Profile {
+    pub name: String,
+}
+
+/// Everything we persist between runs. Fields are defaulted so older files can still be read.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct RecordsFile {
+    pub profiles: Vec<Profile>,
+    pub active_profile: Option<String>,
+    pub games: Vec<GameRecord>,
+}
+
+impl RecordsFile {
+    pub fn read_file(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        ron::de::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes to a temporary file first so a crash can't leave a half-written file behind.
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp_path = path.with_extension("ron.tmp");
+        std::fs::write(&tmp_path, contents)?;
+
+        std::fs::rename(&tmp_path, path)
+    }
+}
+
+/// The platform's per-user data directory for this game, if we can find one.
+pub fn data_dir() -> Option<PathBuf> {
+    let env_dir = |var: &str| {
+        std::env::var_os(var)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+
+    let base = if cfg!(target_os = "windows") {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".local/share")))
+    };
+
+    base.map(|dir| dir.join("projectris"))
+}
+
+/// The persistent high score table and player profiles.
+///
+/// Without a data directory, records still work for the current session but are never saved.
+#[derive(Resource)]
+pub struct Records {
+    path: Option<PathBuf>,
+    file: RecordsFile,
+}
+
+impl Records {
+    /// Missing files start an empty table. Corrupt files are moved aside rather than overwritten, so they can be
+    /// recovered by hand.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file = match &path {
+            Some(path) => match RecordsFile::read_file(path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => RecordsFile::default(),
+                Err(e) => {
+                    warn!("Failed to read records from {}: {}", path.display(), e);
+                    let backup_path = path.with_extension("ron.corrupt");
+                    if let Err(e) = std::fs::rename(path, &backup_path) {
+                        warn!("Failed to back up corrupt records: {}", e);
+                    }
+                    RecordsFile::default()
+                }
+            },
+            None => RecordsFile::default(),
+        };
+
+        Self { path, file }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = self.file.write_file(path) {
+            warn!("Failed to save records to {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn profiles(&self) -> &[Profile] {
+        &self.file.profiles
+    }
+
+    pub fn active_profile(&self) -> &str {
+        self.file
+            .active_profile
+            .as_deref()
+            .unwrap_or(DEFAULT_PROFILE_NAME)
+    }
+
+    /// Returns false if the name is empty or taken.
+    pub fn add_profile(&mut self, name: &str) -> bool {
+        let name = name.trim();
+        if name.is_empty() || self.file.profiles.iter().any(|p| p.name == name) {
+            return false;
+        }
+        self.file.profiles.push(Profile {
+            name: name.to_string(),
+        });
+        self.select_profile(name);
+
+        true
+    }
+
+    pub fn select_profile(&mut self, name: &str) {
+        self.file.active_profile = Some(name.to_string());
+        self.save();
+    }
+
+    pub fn add_game(&mut self, record: GameRecord) {
+        self.file.games.push(record);
+        self.save();
+    }
+
+    /// The best `n` unassisted games of `mode`, best first. Only games with the same goal are comparable.
+    pub fn high_scores(&self, mode: GameMode, goal: Option<u32>, n: usize) -> Vec<&GameRecord> {
+        let mut games: Vec<&GameRecord> = self
+            .file
+            .games
+            .iter()
+            .filter(|g| g.mode == mode && g.goal == goal && !g.assisted)
+            .collect();
+        if mode.ranks_by_time() {
+            games.retain(|g| g.completed);
+            games.sort_by(|a, b| a.duration_secs.total_cmp(&b.duration_secs));
+        } else {
+            games.sort_by_key(|g| std::cmp::Reverse(g.score));
+        }
+        games.truncate(n);
+
+        games
+    }
+
+    /// Whether the active profile has ever solved the puzzle called `name`.
+    pub fn puzzle_solved(&self, name: &str) -> bool {
+        let profile = self.active_profile();
+        self.file.games.iter().any(|g| {
+            g.mode == GameMode::Puzzle
+                && g.completed
+                && g.profile == profile
+                && g.puzzle.as_deref() == Some(name)
+        })
+    }
+
+    pub fn personal_best(
+        &self,
+        profile: &str,
+        mode: GameMode,
+        goal: Option<u32>,
+    ) -> Option<&GameRecord> {
+        self.high_scores(mode, goal, usize::MAX)
+            .into_iter()
+            .find(|g| g.profile == profile)
+    }
+}
+
+/// The active profile's best game in the current mode, used as a "ghost" to race against.
+#[derive(Resource)]
+pub struct PersonalBest(pub GameRecord);
+
+pub fn load_personal_best(
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    records: Res<Records>,
+    mut commands: Commands,
+) {
+    let best = records.personal_best(records.active_profile(), *mode, mode.goal(&config));
+    match best {
+        Some(best) => commands.insert_resource(PersonalBest(best.clone())),
+        None => commands.remove_resource::<PersonalBest>(),
+    }
+}
+
+pub fn load_records(mut commands: Commands) {
+    let path = data_dir().map(|dir| dir.join(RECORDS_FILE_NAME));
+    commands.insert_resource(Records::load(path));
+}
+
+#[allow(clippy::type_complexity)]
+pub fn record_finished_game(
+    config: Res<Config>,
+    board_query: Query<
+        (
+            &Player,
+            &GameStats,
+            &GrabBag,
+            Has<Autoplay>,
+            Option<&PlacementHint>,
+        ),
+        With<Board>,
+    >,
+    mode: Res<GameMode>,
+    reason: Res<GameOverReason>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    mut records: ResMut<Records>,
+) {
+    // Records are only kept for single player games.
+    if mode.players().len() > 1 {
+        return;
+    }
+    let Some((_, stats, grab_bag, autoplayed, hint)) =
+        board_query.iter().find(|(p, ..)| **p == Player::One)
+    else {
+        return;
+    };
+    if autoplayed {
+        return;
+    }
+
+    let finished_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let record = GameRecord {
+        profile: records.active_profile().to_string(),
+        mode: *mode,
+        score: stats.score(),
+        lines: stats.total_lines(),
+        level: stats.level(),
+        duration_secs: stats.elapsed_secs(),
+        seed: grab_bag.seed(),
+        pieces_placed: stats.pieces_placed(),
+        finished_at,
+        completed: *reason == GameOverReason::Completed,
+        goal: mode.goal(&config),
+        line_splits: stats.line_splits().to_vec(),
+        puzzle: puzzle.map(|p| p.0.name.clone()),
+        assisted: hint.is_some_and(PlacementHint::assisted),
+    };
+    records.add_game(record);
+}
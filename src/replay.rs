@@ -0,0 +1,236 @@
+use crate::{
+    Action, Config, FallingPiece, FallingPieceEvent, FallingPieceFeedback, FallingPieceOutcome,
+    GameMode, GameSeed, Player, ResumeGame,
+};
+use bevy::prelude::*;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The file format of a replay: how the game was set up, and every move that was made in it.
+///
+/// A game is replayed by playing the same moves on the same pieces, so it comes out the same however fast it runs. Dig
+/// is the exception, since its garbage rises on a timer.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ReplayFile {
+    pub mode: GameMode,
+    pub seed: u64,
+    pub grid_size: [usize; 2],
+    pub repeats_per_bag: usize,
+    pub moves: Vec<ReplayMove>,
+}
+
+/// A move that was made on a player's falling piece, including the drops from gravity.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ReplayMove {
+    pub player: Player,
+    /// How many pieces the player had placed before this one.
+    pub piece: u32,
+    /// Seconds since the player's previous piece was placed, or since the game started.
+    pub secs: f32,
+    pub action: Action,
+}
+
+impl ReplayFile {
+    pub fn read_file(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        ron::de::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        std::fs::write(path, contents)
+    }
+}
+
+/// Which piece each player is on, and when they got it.
+#[derive(Default)]
+struct PieceCounter(HashMap<Player, (u32, f64)>);
+
+impl PieceCounter {
+    fn current(&self, player: Player) -> (u32, f64) {
+        self.0.get(&player).copied().unwrap_or_default()
+    }
+
+    fn count_placed(&mut self, feedback: &mut EventReader<FallingPieceFeedback>, now: f64) {
+        for FallingPieceFeedback { player, outcome } in feedback.read() {
+            if *outcome == FallingPieceOutcome::Placed {
+                let (piece, _) = self.current(*player);
+                self.0.insert(*player, (piece + 1, now));
+            }
+        }
+    }
+
+    fn start(players: &[Player], now: f64) -> Self {
+        Self(players.iter().map(|p| (*p, (0, now))).collect())
+    }
+}
+
+/// Records every game to `path`, from `--record`. The file is written when the game ends.
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    path: PathBuf,
+    replay: Option<ReplayFile>,
+    pieces: PieceCounter,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            replay: None,
+            pieces: PieceCounter::default(),
+        }
+    }
+}
+
+/// Should run after `create_game` and before `resume_saved_game`. A resumed game isn't recorded, since a replay starts
+/// from an empty board.
+pub fn start_replay_recording(
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    seed: Res<GameSeed>,
+    time: Res<Time>,
+    resume: Option<Res<ResumeGame>>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    if resume.is_some() {
+        recorder.replay = None;
+        return;
+    }
+    recorder.replay = Some(ReplayFile {
+        mode: *mode,
+        seed: seed.0,
+        grid_size: config.grid_size,
+        repeats_per_bag: config.repeats_per_bag,
+        moves: Vec::new(),
+    });
+    let now = time.elapsed_secs_f64();
+    recorder.pieces = PieceCounter::start(mode.players(), now);
+}
+
+/// Runs before `update_falling_piece`, to see the same events it's about to handle. A move only counts if the player
+/// has a falling piece for it to apply to.
+pub fn record_replay_moves(
+    time: Res<Time>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut events: EventReader<FallingPieceEvent>,
+    mut feedback: EventReader<FallingPieceFeedback>,
+    falling_piece_query: Query<&FallingPiece>,
+) {
+    let now = time.elapsed_secs_f64();
+    let recorder = &mut *recorder;
+    recorder.pieces.count_placed(&mut feedback, now);
+    let Some(replay) = &mut recorder.replay else {
+        events.clear();
+        return;
+    };
+
+    for FallingPieceEvent { player, action } in events.read() {
+        let Some(action) = Action::from_falling_piece_action(action) else {
+            continue;
+        };
+        if !falling_piece_query.iter().any(|p| p.player() == *player) {
+            continue;
+        }
+        let (piece, started_at) = recorder.pieces.current(*player);
+        replay.moves.push(ReplayMove {
+            player: *player,
+            piece,
+            secs: (now - started_at) as f32,
+            action,
+        });
+    }
+}
+
+pub fn save_replay(recorder: Res<ReplayRecorder>) {
+    let Some(replay) = &recorder.replay else {
+        return;
+    };
+    match replay.write_file(&recorder.path) {
+        Ok(()) => info!("Saved the replay to {}", recorder.path.display()),
+        Err(e) => warn!(
+            "Failed to save the replay to {}: {}",
+            recorder.path.display(),
+            e
+        ),
+    }
+}
+
+/// Plays a replay from `--replay` in place of the controls and gravity.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    moves: HashMap<Player, VecDeque<ReplayMove>>,
+    pieces: PieceCounter,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: &ReplayFile) -> Self {
+        let mut moves: HashMap<Player, VecDeque<ReplayMove>> = HashMap::new();
+        for m in replay.moves.iter() {
+            moves.entry(m.player).or_default().push_back(m.clone());
+        }
+
+        Self {
+            moves,
+            pieces: PieceCounter::default(),
+        }
+    }
+
+    /// Whether every move has been played.
+    pub fn is_finished(&self) -> bool {
+        self.moves.values().all(VecDeque::is_empty)
+    }
+}
+
+/// Sends each move once the player is on the right piece, and as long after getting it as it was made. Should run
+/// before `update_falling_piece`.
+pub fn play_replay(
+    time: Res<Time>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut feedback: EventReader<FallingPieceFeedback>,
+    mut events: EventWriter<FallingPieceEvent>,
+    falling_piece_query: Query<&FallingPiece>,
+) {
+    let now = time.elapsed_secs_f64();
+    let playback = &mut *playback;
+    playback.pieces.count_placed(&mut feedback, now);
+
+    for (player, moves) in playback.moves.iter_mut() {
+        let (piece, started_at) = playback.pieces.current(*player);
+        // Anything left over from a placed piece was made after it locked, so it never applied.
+        while moves.front().is_some_and(|m| m.piece < piece) {
+            moves.pop_front();
+        }
+        if !falling_piece_query.iter().any(|p| p.player() == *player) {
+            continue;
+        }
+        while let Some(m) = moves.front() {
+            if m.piece != piece || f64::from(m.secs) > now - started_at {
+                break;
+            }
+            events.send(FallingPieceEvent::new(
+                *player,
+                m.action.falling_piece_action(),
+            ));
+            moves.pop_front();
+        }
+    }
+}
+
+/// Starts counting pieces at the start of the game.
+pub fn start_replay_playback(
+    mode: Res<GameMode>,
+    time: Res<Time>,
+    mut playback: ResMut<ReplayPlayback>,
+) {
+    playback.pieces = PieceCounter::start(mode.players(), time.elapsed_secs_f64());
+}
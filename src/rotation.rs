@@ -1,4 +1,4 @@
-use bevy::math::{Quat, Vec3};
+use bevy::math::{IVec3, Quat, Vec3};
 
 #[derive(Clone, Debug)]
 pub struct Rotation {
@@ -32,3 +32,12 @@ impl Rotation {
         }
     }
 }
+
+/// Applies a rotation matrix, given as rows, to a cube offset.
+pub fn rotate_offset(matrix: [[i32; 3]; 3], p: IVec3) -> IVec3 {
+    let x_map = IVec3::from(matrix[0]);
+    let y_map = IVec3::from(matrix[1]);
+    let z_map = IVec3::from(matrix[2]);
+
+    IVec3::new(x_map.dot(p), y_map.dot(p), z_map.dot(p))
+}
@@ -0,0 +1,286 @@
+use crate::{
+    data_dir, redraw_falling_piece, spawn_piece_entity, time_between_drops_for_level, ActivePuzzle,
+    Board, CellValue, Config, DropTimer, FallingPiece, FallingPieceAction, FallingPieceEvent,
+    GameClock, GameMode, GameOverReason, GameSeed, GameStats, GarbageRise, GrabBag, Grid, GridSide,
+    OnlineGame, PendingClears, Player, Puzzle, Remote, ReplayPlayback, SceneAssets,
+};
+use bevy::{
+    prelude::*,
+    window::{WindowCloseRequested, WindowFocused},
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+const SAVE_FILE_NAME: &str = "save.ron";
+
+/// The file format of a game in progress. There's only one: it's overwritten whenever a game is saved, and removed
+/// when a game ends.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct SavedGame {
+    pub mode: GameMode,
+    pub seed: u64,
+    pub grid_size: [usize; 2],
+    pub boards: Vec<SavedBoard>,
+    /// The puzzle being played, in `GameMode::Puzzle`.
+    pub puzzle: Option<Puzzle>,
+}
+
+/// One player's `Board`, along with their grids and falling piece.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct SavedBoard {
+    pub player: Player,
+    pub grids: Vec<SavedGrid>,
+    /// There's no falling piece between pieces, e.g. while rows are being cleared.
+    pub falling_piece: Option<FallingPiece>,
+    pub grab_bag: GrabBag,
+    pub stats: GameStats,
+    pub secs_since_last_drop: f64,
+    pub garbage: Option<GarbageRise>,
+    /// See `PendingClears`. They're only scored once the falling piece is placed, so they have to be kept until then.
+    #[serde(default)]
+    pub pending_clears: Vec<(GridSide, usize)>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct SavedGrid {
+    pub side: GridSide,
+    /// See `Grid::master_cells`.
+    pub cells: Vec<CellValue>,
+    /// Whether the falling piece is still falling in this grid, rather than locked in it.
+    pub active: bool,
+}
+
+impl SavedGame {
+    pub fn path() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join(SAVE_FILE_NAME))
+    }
+
+    pub fn exists() -> bool {
+        Self::path().is_some_and(|path| path.exists())
+    }
+
+    /// Returns `None` if there's no saved game or it can't be read.
+    pub fn load() -> Option<Self> {
+        let path = Self::path()?;
+        match Self::read_file(&path) {
+            Ok(saved) => Some(saved),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed to read the saved game {}: {}", path.display(), e);
+                }
+                None
+            }
+        }
+    }
+
+    pub fn read_file(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        ron::de::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes to a temporary file first so a crash can't leave a half-written file behind.
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp_path = path.with_extension("ron.tmp");
+        std::fs::write(&tmp_path, contents)?;
+
+        std::fs::rename(&tmp_path, path)
+    }
+}
+
+/// The saved game to pick up when entering `GameState::Playing`, from the main menu's Continue button.
+#[derive(Resource)]
+pub struct ResumeGame(pub SavedGame);
+
+/// Run condition for saving. Only a local game that hasn't ended can be resumed; online games, replays and the games
+/// being watched by a spectator can't.
+pub fn game_can_be_saved(
+    game_over: Option<Res<GameOverReason>>,
+    online: Option<Res<OnlineGame>>,
+    playback: Option<Res<ReplayPlayback>>,
+    board_query: Query<(), (With<Board>, Without<Remote>)>,
+) -> bool {
+    game_over.is_none() && online.is_none() && playback.is_none() && !board_query.is_empty()
+}
+
+/// Run condition for saving when the player might not come back: the window lost focus, or the game is closing.
+pub fn game_interrupted(
+    mut focused_events: EventReader<WindowFocused>,
+    mut close_events: EventReader<WindowCloseRequested>,
+    mut exit_events: EventReader<AppExit>,
+) -> bool {
+    let unfocused = focused_events.read().any(|e| !e.focused);
+    let closing = close_events.read().count() > 0;
+    let exiting = exit_events.read().count() > 0;
+
+    unfocused || closing || exiting
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn save_game(
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    seed: Res<GameSeed>,
+    time: Res<Time>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    board_query: Query<
+        (
+            &Player,
+            &GrabBag,
+            &GameStats,
+            &DropTimer,
+            Option<&GarbageRise>,
+            &PendingClears,
+        ),
+        With<Board>,
+    >,
+    grid_query: Query<&Grid>,
+    falling_piece_query: Query<&FallingPiece>,
+) {
+    let Some(path) = SavedGame::path() else {
+        return;
+    };
+    let boards = board_query
+        .iter()
+        .map(
+            |(player, grab_bag, stats, drop_timer, garbage, pending_clears)| SavedBoard {
+                player: *player,
+                grids: grid_query
+                    .iter()
+                    .filter(|g| g.player() == *player)
+                    .map(|g| SavedGrid {
+                        side: g.side(),
+                        cells: g.master_cells().to_vec(),
+                        active: g.is_active(),
+                    })
+                    .collect(),
+                falling_piece: falling_piece_query
+                    .iter()
+                    .find(|p| p.player() == *player)
+                    .copied(),
+                grab_bag: grab_bag.clone(),
+                stats: stats.clone(),
+                secs_since_last_drop: drop_timer.secs_since_last_drop(&time),
+                garbage: garbage.cloned(),
+                pending_clears: pending_clears.0.clone(),
+            },
+        )
+        .collect();
+    let saved = SavedGame {
+        mode: *mode,
+        seed: seed.0,
+        grid_size: config.grid_size,
+        boards,
+        puzzle: puzzle.map(|p| p.0.clone()),
+    };
+
+    match saved.write_file(&path) {
+        Ok(()) => info!("Saved the game to {}", path.display()),
+        Err(e) => warn!("Failed to save the game to {}: {}", path.display(), e),
+    }
+}
+
+/// A game that has ended can't be resumed, so it's no longer worth keeping.
+pub fn remove_saved_game(online: Option<Res<OnlineGame>>, playback: Option<Res<ReplayPlayback>>) {
+    if online.is_some() || playback.is_some() {
+        return;
+    }
+    let Some(path) = SavedGame::path() else {
+        return;
+    };
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Failed to remove the saved game {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Should run after `create_game` and the mode's set up, to replace the new game with the saved one.
+///
+/// Rows that were being cleared when the game was saved are collapsed straight away, and the next piece is spawned if
+/// there wasn't one. Clears that the falling piece hasn't been scored for yet go back to its board's `PendingClears`.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn resume_saved_game(
+    config: Res<Config>,
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+    resume: Res<ResumeGame>,
+    scene_assets: Res<SceneAssets>,
+    mut commands: Commands,
+    mut piece_events: ResMut<Events<FallingPieceEvent>>,
+    mut board_query: Query<
+        (
+            Entity,
+            &Player,
+            &mut GrabBag,
+            &mut GameStats,
+            &mut DropTimer,
+            &mut PendingClears,
+        ),
+        With<Board>,
+    >,
+    mut grid_query: Query<&mut Grid>,
+) {
+    commands.remove_resource::<ResumeGame>();
+    let saved = &resume.0;
+    if saved.grid_size != config.grid_size {
+        warn!(
+            "Starting a new game, because the saved game has a grid size of {:?} and the config has {:?}",
+            saved.grid_size, config.grid_size
+        );
+        return;
+    }
+
+    // The new game's first pieces are replaced by the saved ones.
+    piece_events.clear();
+    commands.insert_resource(GameSeed(saved.seed));
+    if let Some(puzzle) = &saved.puzzle {
+        commands.insert_resource(ActivePuzzle(puzzle.clone()));
+    }
+
+    for (board, player, mut grab_bag, mut stats, mut drop_timer, mut pending_clears) in
+        board_query.iter_mut()
+    {
+        let Some(saved_board) = saved.boards.iter().find(|b| b.player == *player) else {
+            continue;
+        };
+        *grab_bag = saved_board.grab_bag.clone();
+        *stats = saved_board.stats.clone();
+        pending_clears.0 = saved_board.pending_clears.clone();
+        commands.insert_resource(GameClock::start(&real_time, stats.elapsed_secs()));
+        drop_timer.set_time_between_drops(time_between_drops_for_level(stats.level()));
+        drop_timer.set_secs_since_last_drop(saved_board.secs_since_last_drop, &time);
+        if let Some(garbage) = &saved_board.garbage {
+            commands.entity(board).insert(garbage.clone());
+        }
+
+        for mut grid in grid_query.iter_mut().filter(|g| g.player() == *player) {
+            if let Some(saved_grid) = saved_board.grids.iter().find(|g| g.side == grid.side()) {
+                grid.restore(&saved_grid.cells, saved_grid.active);
+            }
+            grid.collapse_full_rows();
+        }
+
+        match saved_board.falling_piece {
+            Some(piece) => {
+                spawn_piece_entity(
+                    piece,
+                    &scene_assets.piece_materials,
+                    scene_assets.cube_mesh.clone(),
+                    &mut commands,
+                );
+                redraw_falling_piece(&piece, &mut grid_query);
+            }
+            None => {
+                piece_events.send(FallingPieceEvent::new(*player, FallingPieceAction::Spawn));
+            }
+        }
+    }
+}
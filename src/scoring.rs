@@ -0,0 +1,190 @@
+use crate::{
+    is_dual_clear, Board, FallingPieceFeedback, FallingPieceOutcome, GridSide, PieceCleared, Player,
+};
+use bevy::prelude::*;
+
+const LINES_PER_LEVEL: u32 = 10;
+const LINES_PER_SPLIT: u32 = 10;
+/// Indexed by the number of rows cleared in one grid minus one.
+const LINE_CLEAR_POINTS: [u64; 4] = [100, 300, 500, 800];
+const DUAL_CLEAR_MULTIPLIER: u64 = 2;
+
+/// Everything we keep track of for one player during a single game. Every new game starts with a fresh copy.
+#[derive(Clone, Component, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct GameStats {
+    score: u64,
+    left_lines: u32,
+    right_lines: u32,
+    pieces_placed: u32,
+    elapsed_secs: f64,
+    line_splits: Vec<f64>,
+}
+
+impl GameStats {
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn level(&self) -> u32 {
+        1 + self.total_lines() / LINES_PER_LEVEL
+    }
+
+    pub fn lines(&self, side: GridSide) -> u32 {
+        match side {
+            GridSide::Left => self.left_lines,
+            GridSide::Right => self.right_lines,
+        }
+    }
+
+    pub fn total_lines(&self) -> u32 {
+        self.left_lines + self.right_lines
+    }
+
+    pub fn pieces_placed(&self) -> u32 {
+        self.pieces_placed
+    }
+
+    pub fn elapsed_secs(&self) -> f64 {
+        self.elapsed_secs
+    }
+
+    /// The elapsed time when every multiple of 10 total lines was reached.
+    pub fn line_splits(&self) -> &[f64] {
+        &self.line_splits
+    }
+
+    pub fn pieces_per_second(&self) -> f64 {
+        if self.elapsed_secs > 0.0 {
+            self.pieces_placed as f64 / self.elapsed_secs
+        } else {
+            0.0
+        }
+    }
+
+    pub fn add_placed_piece(&mut self) {
+        self.pieces_placed += 1;
+    }
+
+    /// Scores the clears from one piece, as `(side, count)` pairs, and returns how many points they were worth.
+    pub fn add_clears(&mut self, clears: &[(GridSide, usize)]) -> u64 {
+        let multiplier = if is_dual_clear(clears) {
+            DUAL_CLEAR_MULTIPLIER
+        } else {
+            1
+        };
+
+        // Points are awarded at the level the clear happened on.
+        let level = self.level();
+        let mut points = 0;
+        for (side, count) in clears.iter().cloned() {
+            points += multiplier * line_clear_points(count, level);
+            self.add_lines(side, count as u32);
+        }
+        self.score += points;
+
+        points
+    }
+
+    fn add_lines(&mut self, side: GridSide, count: u32) {
+        match side {
+            GridSide::Left => self.left_lines += count,
+            GridSide::Right => self.right_lines += count,
+        }
+
+        // A single clear can cross more than one split.
+        while (self.line_splits.len() as u32 + 1) * LINES_PER_SPLIT <= self.total_lines() {
+            self.line_splits.push(self.elapsed_secs);
+        }
+    }
+}
+
+pub fn line_clear_points(count: usize, level: u32) -> u64 {
+    let base = LINE_CLEAR_POINTS[count.clamp(1, LINE_CLEAR_POINTS.len()) - 1];
+
+    base * level as u64
+}
+
+/// Measures the game in progress on the wall clock. `Time<Virtual>` caps each frame's delta, so a stalled frame would
+/// otherwise lose time. Time spent paused doesn't count.
+#[derive(Resource)]
+pub struct GameClock {
+    /// When the game started, in `Time<Real>` seconds. A resumed game started before the app did, so it can be
+    /// negative.
+    started_secs: f64,
+    paused_secs: f64,
+    paused_at: Option<f64>,
+}
+
+impl GameClock {
+    /// Starts the clock as if `elapsed_secs` had already passed, e.g. for a resumed game.
+    pub fn start(time: &Time<Real>, elapsed_secs: f64) -> Self {
+        Self {
+            started_secs: time.elapsed_secs_f64() - elapsed_secs,
+            paused_secs: 0.0,
+            paused_at: None,
+        }
+    }
+
+    pub fn pause(&mut self, time: &Time<Real>) {
+        self.paused_at.get_or_insert(time.elapsed_secs_f64());
+    }
+
+    pub fn unpause(&mut self, time: &Time<Real>) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_secs += time.elapsed_secs_f64() - paused_at;
+        }
+    }
+
+    pub fn elapsed_secs(&self, time: &Time<Real>) -> f64 {
+        let now = self.paused_at.unwrap_or(time.elapsed_secs_f64());
+
+        now - self.started_secs - self.paused_secs
+    }
+}
+
+#[derive(Clone, Copy, Debug, Event)]
+pub struct LevelUp {
+    pub player: Player,
+    pub level: u32,
+}
+
+pub fn update_game_stats(
+    time: Res<Time<Real>>,
+    clock: Res<GameClock>,
+    mut board_query: Query<(&Player, &mut GameStats), With<Board>>,
+    mut feedback: EventReader<FallingPieceFeedback>,
+    mut piece_cleared: EventReader<PieceCleared>,
+    mut level_ups: EventWriter<LevelUp>,
+) {
+    let elapsed_secs = clock.elapsed_secs(&time);
+    for (_, mut stats) in board_query.iter_mut() {
+        stats.elapsed_secs = elapsed_secs;
+    }
+
+    for event in feedback.read() {
+        if event.outcome != FallingPieceOutcome::Placed {
+            continue;
+        }
+        for (_, mut stats) in board_query.iter_mut().filter(|(p, _)| **p == event.player) {
+            stats.add_placed_piece();
+        }
+    }
+
+    for PieceCleared { player, clears } in piece_cleared.read() {
+        let player = *player;
+        let Some((_, mut stats)) = board_query.iter_mut().find(|(p, _)| **p == player) else {
+            continue;
+        };
+
+        let old_level = stats.level();
+        stats.add_clears(clears);
+
+        let new_level = stats.level();
+        if new_level > old_level {
+            level_ups.send(LevelUp {
+                player,
+                level: new_level,
+            });
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{
+    BoardSnapshot, ClientMessage, Connection, GameMode, Player, ServerMessage, ALL_PLAYERS,
+    PROTOCOL_VERSION,
+};
+use std::{
+    io,
+    net::{SocketAddr, TcpListener},
+    time::Duration,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// A connection that hasn't said what it's here for yet, or a player that hasn't been matched with an opponent yet.
+/// `grid_size` is known once a player says hello.
+struct Waiting {
+    connection: Connection,
+    address: SocketAddr,
+    grid_size: Option<[usize; 2]>,
+}
+
+/// Two players in a game, indexed by `Player::index`.
+struct Match {
+    id: u64,
+    connections: [Connection; 2],
+}
+
+/// A local game that sends its boards to the server so they can be watched.
+struct Broadcast {
+    connection: Connection,
+    address: SocketAddr,
+    grid_size: [usize; 2],
+    /// The game being played right now, if any.
+    game: Option<u64>,
+}
+
+/// Everyone watching, and the game they're watching. Spectators always watch the most recently started game.
+#[derive(Default)]
+struct Spectators {
+    connections: Vec<Connection>,
+    featured: Option<FeaturedGame>,
+}
+
+struct FeaturedGame {
+    id: u64,
+    grid_size: [usize; 2],
+    mode: GameMode,
+}
+
+/// Pairs up players as they connect and relays messages between them. The server doesn't run the game itself; each
+/// client simulates its own board and sends snapshots of it to the opponent and any spectators.
+pub struct Server {
+    listener: TcpListener,
+    waiting: Vec<Waiting>,
+    matches: Vec<Match>,
+    broadcasts: Vec<Broadcast>,
+    spectators: Spectators,
+    next_game_id: u64,
+}
+
+impl Server {
+    pub fn new(listener: TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            waiting: Vec::new(),
+            matches: Vec::new(),
+            broadcasts: Vec::new(),
+            spectators: Spectators::default(),
+            next_game_id: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.poll()?;
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Does whatever work is ready without blocking. Only errors from the listener itself are returned; a broken
+    /// client connection just ends that client's game.
+    pub fn poll(&mut self) -> io::Result<()> {
+        self.accept_connections()?;
+        self.greet_waiting();
+        self.start_matches();
+        self.relay_matches();
+        self.relay_broadcasts();
+        self.spectators.flush();
+
+        Ok(())
+    }
+
+    fn accept_connections(&mut self) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => match Connection::new(stream) {
+                    Ok(connection) => {
+                        println!("{} connected", address);
+                        self.waiting.push(Waiting {
+                            connection,
+                            address,
+                            grid_size: None,
+                        });
+                    }
+                    Err(e) => println!("Dropping {}: {}", address, e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn new_game_id(&mut self) -> u64 {
+        self.next_game_id += 1;
+
+        self.next_game_id
+    }
+
+    /// Players stay in `waiting` until they're matched. Broadcasting games and spectators move on right away.
+    fn greet_waiting(&mut self) {
+        let mut i = 0;
+        while i < self.waiting.len() {
+            let waiting = &mut self.waiting[i];
+            let messages = match waiting.connection.receive::<ClientMessage>() {
+                Ok(messages) => messages,
+                Err(e) => {
+                    println!("{} left the lobby: {}", waiting.address, e);
+                    self.waiting.remove(i);
+                    continue;
+                }
+            };
+
+            let mut greeting = None;
+            let mut rejected = false;
+            let mut messages = messages.into_iter();
+            for message in messages.by_ref() {
+                let version = match message {
+                    ClientMessage::Hello { version, .. }
+                    | ClientMessage::Broadcast { version, .. }
+                    | ClientMessage::Spectate { version } => version,
+                    // Leftovers from a game that already ended.
+                    _ => continue,
+                };
+                if version != PROTOCOL_VERSION {
+                    let reason = format!(
+                        "The server speaks protocol version {}, but the client speaks {}",
+                        PROTOCOL_VERSION, version
+                    );
+                    println!("Rejecting {}: {}", waiting.address, reason);
+                    waiting.connection.send(&ServerMessage::Rejected(reason));
+                    rejected = true;
+                    break;
+                }
+                let is_broadcast = matches!(message, ClientMessage::Broadcast { .. });
+                greeting = Some(message);
+                // A broadcasting game may have sent its first game along with the greeting.
+                if is_broadcast {
+                    break;
+                }
+            }
+
+            match greeting.filter(|_| !rejected) {
+                Some(ClientMessage::Hello { grid_size, .. }) => {
+                    waiting.grid_size = Some(grid_size);
+                }
+                Some(ClientMessage::Broadcast { grid_size, .. }) => {
+                    let waiting = self.waiting.remove(i);
+                    println!("{} is broadcasting", waiting.address);
+                    self.broadcasts.push(Broadcast {
+                        connection: waiting.connection,
+                        address: waiting.address,
+                        grid_size,
+                        game: None,
+                    });
+                    self.handle_broadcast_messages(self.broadcasts.len() - 1, messages);
+                    continue;
+                }
+                Some(ClientMessage::Spectate { .. }) => {
+                    let waiting = self.waiting.remove(i);
+                    println!("{} is spectating", waiting.address);
+                    self.spectators.add(waiting.connection);
+                    continue;
+                }
+                _ => {}
+            }
+
+            // Dropping a rejected connection sends the reason on its way.
+            if rejected || waiting.connection.flush().is_err() {
+                self.waiting.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn start_matches(&mut self) {
+        let mut i = 0;
+        while i < self.waiting.len() {
+            let Some(grid_size) = self.waiting[i].grid_size else {
+                i += 1;
+                continue;
+            };
+            let Some(j) =
+                (i + 1..self.waiting.len()).find(|j| self.waiting[*j].grid_size == Some(grid_size))
+            else {
+                i += 1;
+                continue;
+            };
+
+            // Remove the later one first so the earlier index stays valid.
+            let two = self.waiting.remove(j);
+            let one = self.waiting.remove(i);
+            println!(
+                "Starting a game between {} and {}",
+                one.address, two.address
+            );
+
+            let seed = rand::random();
+            let mut connections = [one.connection, two.connection];
+            for (connection, player) in connections.iter_mut().zip(ALL_PLAYERS.iter().cloned()) {
+                connection.send(&ServerMessage::Start { player, seed });
+            }
+            let id = self.new_game_id();
+            self.spectators.feature(id, grid_size, GameMode::Versus);
+            self.matches.push(Match { id, connections });
+        }
+    }
+
+    fn relay_matches(&mut self) {
+        let spectators = &mut self.spectators;
+        self.matches.retain_mut(|game| game.relay(spectators));
+    }
+
+    fn relay_broadcasts(&mut self) {
+        let mut i = 0;
+        while i < self.broadcasts.len() {
+            let broadcast = &mut self.broadcasts[i];
+            let messages = match broadcast.connection.receive::<ClientMessage>() {
+                Ok(messages) => messages,
+                Err(e) => {
+                    println!("{} stopped broadcasting: {}", broadcast.address, e);
+                    if let Some(game) = broadcast.game {
+                        self.spectators.finish(game);
+                    }
+                    self.broadcasts.remove(i);
+                    continue;
+                }
+            };
+
+            self.handle_broadcast_messages(i, messages);
+            i += 1;
+        }
+    }
+
+    fn handle_broadcast_messages(
+        &mut self,
+        i: usize,
+        messages: impl IntoIterator<Item = ClientMessage>,
+    ) {
+        for message in messages {
+            match message {
+                ClientMessage::Started { mode } => {
+                    let id = self.new_game_id();
+                    let broadcast = &mut self.broadcasts[i];
+                    broadcast.game = Some(id);
+                    self.spectators.feature(id, broadcast.grid_size, mode);
+                }
+                ClientMessage::PlayerBoard { player, snapshot } => {
+                    if let Some(game) = self.broadcasts[i].game {
+                        self.spectators.board(game, player, snapshot);
+                    }
+                }
+                ClientMessage::Finished => {
+                    if let Some(game) = self.broadcasts[i].game.take() {
+                        self.spectators.finish(game);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Match {
+    /// Returns false once the game is over.
+    fn relay(&mut self, spectators: &mut Spectators) -> bool {
+        for player in ALL_PLAYERS {
+            let messages = match self.connections[player.index()].receive::<ClientMessage>() {
+                Ok(messages) => messages,
+                Err(_) => {
+                    self.send(player.opponent(), ServerMessage::OpponentLeft);
+                    spectators.finish(self.id);
+                    return false;
+                }
+            };
+            for message in messages {
+                match message {
+                    ClientMessage::Board(snapshot) => {
+                        spectators.board(self.id, player, snapshot.clone());
+                        self.send(player.opponent(), ServerMessage::OpponentBoard(snapshot));
+                    }
+                    ClientMessage::Garbage(rows) => {
+                        self.send(player.opponent(), ServerMessage::Garbage(rows));
+                    }
+                    ClientMessage::Lost => {
+                        let winner = player.opponent();
+                        self.send(Player::One, ServerMessage::End { winner });
+                        self.send(Player::Two, ServerMessage::End { winner });
+                        spectators.finish(self.id);
+                        return false;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.connections.iter_mut().all(|c| c.flush().is_ok())
+    }
+
+    fn send(&mut self, player: Player, message: ServerMessage) {
+        self.connections[player.index()].send(&message);
+    }
+}
+
+impl Spectators {
+    fn add(&mut self, mut connection: Connection) {
+        if let Some(featured) = &self.featured {
+            connection.send(&featured.watching());
+        }
+        self.connections.push(connection);
+    }
+
+    fn feature(&mut self, id: u64, grid_size: [usize; 2], mode: GameMode) {
+        let featured = FeaturedGame {
+            id,
+            grid_size,
+            mode,
+        };
+        self.send(&featured.watching());
+        self.featured = Some(featured);
+    }
+
+    fn board(&mut self, game: u64, player: Player, snapshot: BoardSnapshot) {
+        if self.is_featured(game) {
+            self.send(&ServerMessage::PlayerBoard { player, snapshot });
+        }
+    }
+
+    fn finish(&mut self, game: u64) {
+        if self.is_featured(game) {
+            self.send(&ServerMessage::Finished);
+            self.featured = None;
+        }
+    }
+
+    fn is_featured(&self, game: u64) -> bool {
+        self.featured.as_ref().is_some_and(|f| f.id == game)
+    }
+
+    fn send(&mut self, message: &ServerMessage) {
+        for connection in self.connections.iter_mut() {
+            connection.send(message);
+        }
+    }
+
+    /// Spectators never say anything after `Spectate`, so reading is only for noticing when they leave.
+    fn flush(&mut self) {
+        self.connections.retain_mut(|connection| {
+            connection.receive::<ClientMessage>().is_ok() && connection.flush().is_ok()
+        });
+    }
+}
+
+impl FeaturedGame {
+    fn watching(&self) -> ServerMessage {
+        ServerMessage::Watching {
+            grid_size: self.grid_size,
+            mode: self.mode,
+        }
+    }
+}
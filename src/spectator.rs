@@ -0,0 +1,289 @@
+use crate::{
+    board_snapshot, create_grids, show_board_snapshot, Board, BoardSnapshot, CameraPose,
+    ClientMessage, Config, Connection, FallingPiece, GameMode, GameOver, GameStats, GarbageRise,
+    Grid, LobbyStatus, Player, Remote, SceneAssets, ServerMessage, SnapshotPiece, PROTOCOL_VERSION,
+};
+use bevy::{
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
+    prelude::*,
+};
+use std::collections::VecDeque;
+
+/// World units per second.
+const FREE_CAMERA_SPEED: f32 = 15.0;
+/// Radians per pixel of mouse movement.
+const FREE_CAMERA_LOOK_SPEED: f32 = 0.005;
+/// World units per line of scrolling.
+const FREE_CAMERA_ZOOM_SPEED: f32 = 2.0;
+
+/// Lets spectators watch the games played here, from `--broadcast`.
+#[derive(Resource)]
+pub struct Broadcaster {
+    connection: Connection,
+    last_snapshots: Vec<(Player, BoardSnapshot)>,
+}
+
+impl Broadcaster {
+    pub fn connect(address: &str, grid_size: [usize; 2]) -> std::io::Result<Self> {
+        let mut connection = Connection::connect(address)?;
+        connection.send(&ClientMessage::Broadcast {
+            version: PROTOCOL_VERSION,
+            grid_size,
+        });
+
+        Ok(Self {
+            connection,
+            last_snapshots: Vec::new(),
+        })
+    }
+}
+
+pub fn broadcast_game_start(mode: Res<GameMode>, mut broadcaster: ResMut<Broadcaster>) {
+    broadcaster.last_snapshots.clear();
+    broadcaster
+        .connection
+        .send(&ClientMessage::Started { mode: *mode });
+}
+
+/// Sends every board whenever it changes, just like an online game sends the local board to the opponent.
+pub fn broadcast_boards(
+    mut broadcaster: ResMut<Broadcaster>,
+    board_query: Query<(&Player, &GameStats, Option<&GarbageRise>), With<Board>>,
+    grid_query: Query<&Grid>,
+    falling_piece_query: Query<&FallingPiece>,
+    mut game_over: EventReader<GameOver>,
+    mut commands: Commands,
+) {
+    let broadcaster = &mut *broadcaster;
+    for (player, stats, garbage) in board_query.iter() {
+        let incoming_garbage = garbage.map_or(0, |g| g.pending_rows());
+        let snapshot = board_snapshot(
+            *player,
+            stats,
+            incoming_garbage,
+            &grid_query,
+            &falling_piece_query,
+        );
+        let last = broadcaster
+            .last_snapshots
+            .iter_mut()
+            .find(|(p, _)| p == player);
+        match last {
+            Some((_, last)) if !snapshot.looks_different(last) => continue,
+            Some((_, last)) => *last = snapshot.clone(),
+            None => broadcaster.last_snapshots.push((*player, snapshot.clone())),
+        }
+        broadcaster.connection.send(&ClientMessage::PlayerBoard {
+            player: *player,
+            snapshot,
+        });
+    }
+
+    if game_over.read().count() > 0 {
+        broadcaster.connection.send(&ClientMessage::Finished);
+    }
+
+    let connection = &mut broadcaster.connection;
+    let result = connection
+        .flush()
+        .and_then(|()| connection.receive::<ServerMessage>());
+    let error = match result {
+        Ok(messages) => messages.into_iter().find_map(|m| match m {
+            ServerMessage::Rejected(reason) => Some(reason),
+            _ => None,
+        }),
+        Err(e) => Some(e.to_string()),
+    };
+    if let Some(error) = error {
+        warn!("Stopped broadcasting: {}", error);
+        commands.remove_resource::<Broadcaster>();
+    }
+}
+
+/// The server to watch games on, from `--spectate`.
+#[derive(Resource)]
+pub struct SpectatedServer(pub String);
+
+#[derive(Resource)]
+pub struct SpectatorClient {
+    connection: Connection,
+    /// Messages that arrived after a `Watching`, waiting for the new game's boards to be created.
+    pending: VecDeque<ServerMessage>,
+}
+
+/// Sent when the server moves spectators on to a new game.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct StartWatching {
+    pub grid_size: [usize; 2],
+    pub mode: GameMode,
+}
+
+pub fn connect_as_spectator(server: Res<SpectatedServer>, mut commands: Commands) {
+    match Connection::connect(&server.0) {
+        Ok(mut connection) => {
+            connection.send(&ClientMessage::Spectate {
+                version: PROTOCOL_VERSION,
+            });
+            commands.insert_resource(SpectatorClient {
+                connection,
+                pending: VecDeque::new(),
+            });
+            commands.insert_resource(LobbyStatus(format!(
+                "Waiting for a game to start on {}",
+                server.0
+            )));
+        }
+        Err(e) => {
+            commands.insert_resource(LobbyStatus(format!(
+                "Couldn't connect to {}: {}",
+                server.0, e
+            )));
+        }
+    }
+}
+
+/// Draws the boards of the watched game. Nothing is simulated here; every board is a copy of the snapshots its game
+/// sends.
+#[allow(clippy::too_many_arguments)]
+pub fn receive_spectated_game(
+    mut client: ResMut<SpectatorClient>,
+    mut status: ResMut<LobbyStatus>,
+    mut board_query: Query<(&Player, &mut GameStats, &mut Remote), With<Board>>,
+    mut grid_query: Query<&mut Grid>,
+    snapshot_piece_query: Query<(Entity, &Player), With<SnapshotPiece>>,
+    scene_assets: Res<SceneAssets>,
+    mut commands: Commands,
+    mut start_watching: EventWriter<StartWatching>,
+) {
+    let client = &mut *client;
+    let connection = &mut client.connection;
+    match connection
+        .flush()
+        .and_then(|()| connection.receive::<ServerMessage>())
+    {
+        Ok(messages) => client.pending.extend(messages),
+        Err(e) => {
+            status.0 = format!("Lost the connection to the server: {}", e);
+            commands.remove_resource::<SpectatorClient>();
+            return;
+        }
+    }
+
+    // Only the latest snapshot of each board is worth drawing.
+    let mut latest_snapshots: Vec<(Player, BoardSnapshot)> = Vec::new();
+    while let Some(message) = client.pending.pop_front() {
+        match message {
+            ServerMessage::Watching { grid_size, mode } => {
+                start_watching.send(StartWatching { grid_size, mode });
+                status.0 = format!("Watching a game of {}", mode.name());
+                break;
+            }
+            ServerMessage::PlayerBoard { player, snapshot } => {
+                latest_snapshots.retain(|(p, _)| *p != player);
+                latest_snapshots.push((player, snapshot));
+            }
+            ServerMessage::Finished => {
+                status.0 = "The game is over. Waiting for the next one".to_string();
+            }
+            ServerMessage::Rejected(reason) => {
+                status.0 = format!("The server turned us away: {}", reason);
+                commands.remove_resource::<SpectatorClient>();
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    for (player, snapshot) in latest_snapshots {
+        show_board_snapshot(
+            player,
+            &snapshot,
+            &mut grid_query,
+            &snapshot_piece_query,
+            &scene_assets,
+            &mut commands,
+        );
+        for (_, mut stats, mut remote) in board_query.iter_mut().filter(|(p, ..)| **p == player) {
+            remote.update(&snapshot, &mut stats);
+        }
+    }
+}
+
+/// Should run after `teardown_game` when a `StartWatching` event is received.
+pub fn watch_game(
+    config: Res<Config>,
+    scene_assets: Res<SceneAssets>,
+    mut mode: ResMut<GameMode>,
+    mut events: EventReader<StartWatching>,
+    mut commands: Commands,
+) {
+    let Some(event) = events.read().last() else {
+        return;
+    };
+
+    *mode = event.mode;
+    for player in event.mode.players().iter().cloned() {
+        create_grids(
+            player,
+            event.grid_size,
+            &config,
+            &scene_assets,
+            &mut commands,
+        );
+        commands.spawn((Board, player, GameStats::default(), Remote::default()));
+    }
+}
+
+/// Where a spectator's camera is, relative to each board. Every player's camera moves together.
+#[derive(Resource)]
+pub struct FreeCamera(CameraPose);
+
+/// Starts out where the players' cameras are.
+pub fn reset_free_camera(config: Res<Config>, mut commands: Commands) {
+    commands.insert_resource(FreeCamera(CameraPose::from_config(&config)));
+}
+
+/// W, A, S and D fly around, Q and E go down and up, dragging with the right mouse button looks around and scrolling
+/// zooms. `Config::camera_keys` jump to the same presets as the players' camera, or back to the start.
+#[allow(clippy::too_many_arguments)]
+pub fn fly_free_camera(
+    config: Res<Config>,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut free_camera: ResMut<FreeCamera>,
+    mut camera_query: Query<(&mut Transform, &Player), With<Camera3d>>,
+) {
+    let mut pose = CameraPose::from_keys(&keyboard, &config).unwrap_or(free_camera.0);
+
+    if mouse_buttons.pressed(MouseButton::Right) {
+        pose.look_around(-mouse_motion.delta * FREE_CAMERA_LOOK_SPEED);
+    }
+
+    let rotation = pose.rotation();
+    let forward = rotation * -Vec3::Z;
+    let right = rotation * Vec3::X;
+    let mut direction = Vec3::ZERO;
+    for (key, dir) in [
+        (KeyCode::KeyW, forward),
+        (KeyCode::KeyS, -forward),
+        (KeyCode::KeyD, right),
+        (KeyCode::KeyA, -right),
+        (KeyCode::KeyE, Vec3::Y),
+        (KeyCode::KeyQ, -Vec3::Y),
+    ]
+    .iter()
+    {
+        if keyboard.pressed(*key) {
+            direction += *dir;
+        }
+    }
+    // Flying moves the focus along with the camera, so the camera keeps looking the same way.
+    pose.focus += direction.normalize_or_zero() * FREE_CAMERA_SPEED * time.delta_secs()
+        + forward * mouse_scroll.delta.y * FREE_CAMERA_ZOOM_SPEED;
+
+    free_camera.0 = pose;
+    pose.place_cameras(&mut camera_query);
+}
@@ -0,0 +1,173 @@
+use crate::{asset_file_path, PieceType, ALL_PIECE_TYPES};
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+use std::path::Path;
+
+const PATTERN_SIZE: u32 = 32;
+const PATTERN_BORDER: u32 = 2;
+/// How much a pattern darkens the color under it, from 0 to 255.
+const PATTERN_SHADE: u8 = 110;
+
+/// The file format of a theme. Colors are hex strings like "#ff8000", and textures are paths relative to the `assets`
+/// directory, like the theme itself, multiplied by the colors.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Theme {
+    /// One per piece type, in the order of `ALL_PIECE_TYPES`.
+    pub piece_colors: Vec<String>,
+    pub empty_cell_color: String,
+    pub drop_hint_color: String,
+    pub suggested_hint_color: String,
+    pub line_clear_color: String,
+    pub garbage_color: String,
+    pub background_color: String,
+    pub piece_texture: Option<String>,
+    pub cell_texture: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let hex = |s: &str| s.to_string();
+
+        Self {
+            piece_colors: [
+                "#ff0000", "#008000", "#0000ff", "#ffff00", "#e0ffff", "#ffc0cb", "#ffa500",
+                "#800080",
+            ]
+            .map(hex)
+            .to_vec(),
+            empty_cell_color: hex("#808080"),
+            drop_hint_color: hex("#a9a9a9"),
+            suggested_hint_color: hex("#008b8b"),
+            line_clear_color: hex("#ffffff"),
+            garbage_color: hex("#8b4513"),
+            background_color: hex("#2b2c2f"),
+            piece_texture: Some("BorderedTile.png".into()),
+            cell_texture: None,
+        }
+    }
+}
+
+impl Theme {
+    pub fn read_file(path: &Path) -> Result<Self, ron::Error> {
+        let reader = std::fs::File::open(path)?;
+
+        ron::de::from_reader(reader)
+    }
+
+    /// Parses every color, which also checks that there's one for each piece type.
+    pub fn colors(&self) -> Result<ThemeColors, String> {
+        if self.piece_colors.len() != ALL_PIECE_TYPES.len() {
+            return Err(format!(
+                "expected {} piece colors, found {}",
+                ALL_PIECE_TYPES.len(),
+                self.piece_colors.len()
+            ));
+        }
+
+        Ok(ThemeColors {
+            pieces: self
+                .piece_colors
+                .iter()
+                .map(|c| parse_color(c))
+                .collect::<Result<_, _>>()?,
+            empty_cell: parse_color(&self.empty_cell_color)?,
+            drop_hint: parse_color(&self.drop_hint_color)?,
+            suggested_hint: parse_color(&self.suggested_hint_color)?,
+            line_clear: parse_color(&self.line_clear_color)?,
+            garbage: parse_color(&self.garbage_color)?,
+            background: parse_color(&self.background_color)?,
+        })
+    }
+}
+
+/// The colors of a `Theme`, parsed.
+#[derive(Clone, Debug)]
+pub struct ThemeColors {
+    pieces: Vec<Color>,
+    pub empty_cell: Color,
+    pub drop_hint: Color,
+    pub suggested_hint: Color,
+    pub line_clear: Color,
+    pub garbage: Color,
+    pub background: Color,
+}
+
+impl ThemeColors {
+    pub fn piece(&self, piece_type: PieceType) -> Color {
+        self.pieces[piece_type as usize]
+    }
+}
+
+fn parse_color(hex: &str) -> Result<Color, String> {
+    Srgba::hex(hex)
+        .map(Color::from)
+        .map_err(|e| format!("bad color {:?}: {}", hex, e))
+}
+
+/// `path` is relative to the `assets` directory. A missing or broken theme is not fatal; the game falls back to the
+/// default theme.
+pub fn read_theme(path: &str) -> (Theme, ThemeColors) {
+    let path = asset_file_path(path);
+    let theme = Theme::read_file(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|theme| theme.colors().map(|colors| (theme, colors)));
+
+    theme.unwrap_or_else(|e| {
+        warn!("Failed to read theme {}: {}", path.display(), e);
+        let theme = Theme::default();
+        let colors = theme.colors().expect("the default theme is valid");
+        (theme, colors)
+    })
+}
+
+/// A white tile with a pattern that's different for each piece type, so that pieces can be told apart without their
+/// colors. It's multiplied by the piece's color like any other texture. Pieces get a border as well, like
+/// `BorderedTile.png`.
+pub fn pattern_texture(piece_type: PieceType, bordered: bool) -> Image {
+    let mut data = Vec::with_capacity((PATTERN_SIZE * PATTERN_SIZE * 4) as usize);
+    let inside = PATTERN_BORDER..PATTERN_SIZE - PATTERN_BORDER;
+    for y in 0..PATTERN_SIZE {
+        for x in 0..PATTERN_SIZE {
+            let on_border = bordered && !(inside.contains(&x) && inside.contains(&y));
+            let shade = if on_border || in_pattern(piece_type, x, y) {
+                PATTERN_SHADE
+            } else {
+                u8::MAX
+            };
+            data.extend_from_slice(&[shade, shade, shade, u8::MAX]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: PATTERN_SIZE,
+            height: PATTERN_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+/// Every pattern is symmetric enough to read the same in the left grid, which is seen from behind.
+fn in_pattern(piece_type: PieceType, x: u32, y: u32) -> bool {
+    let center = PATTERN_SIZE / 2;
+    let [dx, dy] = [x.abs_diff(center), y.abs_diff(center)];
+    match piece_type {
+        PieceType::Stick => false,
+        PieceType::Box => dx.max(dy) < 6,
+        PieceType::Jay => y % 8 < 3,
+        PieceType::Zee => x % 8 < 3,
+        PieceType::Tee => (x + y) % 8 < 3 || (x + PATTERN_SIZE - y) % 8 < 3,
+        PieceType::Chi => dx < 2 || dy < 2,
+        PieceType::Ral => (x / 8 + y / 8).is_multiple_of(2),
+        PieceType::Legs => (x % 8).abs_diff(4) < 2 && (y % 8).abs_diff(4) < 2,
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{
+    is_dual_clear, Board, GameMode, GameSeed, GarbageRise, GridSide, PieceCleared, Player, Remote,
+};
+use bevy::prelude::*;
+
+/// Garbage rows sent to the opponent, indexed by the number of rows cleared in one grid minus one.
+const GARBAGE_FOR_CLEAR: [u32; 4] = [0, 1, 2, 4];
+/// Extra rows for clearing in both grids with the same piece.
+const DUAL_CLEAR_GARBAGE_BONUS: u32 = 2;
+
+pub fn garbage_for_clears(clears: &[(GridSide, usize)]) -> u32 {
+    let rows: u32 = clears
+        .iter()
+        .map(|(_, count)| GARBAGE_FOR_CLEAR[(*count).clamp(1, GARBAGE_FOR_CLEAR.len()) - 1])
+        .sum();
+    if is_dual_clear(clears) {
+        rows + DUAL_CLEAR_GARBAGE_BONUS
+    } else {
+        rows
+    }
+}
+
+/// Garbage for an opponent whose board isn't simulated on this machine.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct OutgoingGarbage {
+    pub player: Player,
+    pub rows: u32,
+}
+
+/// Should run after `create_game`. Both boards start empty, and only receive garbage from each other.
+pub fn set_up_versus(
+    mode: Res<GameMode>,
+    seed: Res<GameSeed>,
+    board_query: Query<Entity, (With<Board>, Without<Remote>)>,
+    mut commands: Commands,
+) {
+    if *mode != GameMode::Versus {
+        return;
+    }
+
+    // Both players get the same holes, just like they get the same pieces. Online, the server picks the seed.
+    for board in board_query.iter() {
+        commands
+            .entity(board)
+            .insert(GarbageRise::new(None, seed.0));
+    }
+}
+
+/// Clears first cancel garbage that's on its way to the player, and whatever is left over goes to the opponent.
+pub fn send_versus_garbage(
+    mut piece_cleared: EventReader<PieceCleared>,
+    mut board_query: Query<(&Player, &mut GarbageRise), With<Board>>,
+    mut outgoing_garbage: EventWriter<OutgoingGarbage>,
+) {
+    for PieceCleared { player, clears } in piece_cleared.read() {
+        let player = *player;
+        let mut rows = garbage_for_clears(clears);
+        if let Some((_, mut own_garbage)) = board_query.iter_mut().find(|(p, _)| **p == player) {
+            rows = own_garbage.cancel_rows(rows);
+        }
+        if rows == 0 {
+            continue;
+        }
+        match board_query
+            .iter_mut()
+            .find(|(p, _)| **p == player.opponent())
+        {
+            Some((_, mut opponent_garbage)) => opponent_garbage.add_rows(rows),
+            None => {
+                outgoing_garbage.send(OutgoingGarbage {
+                    player: player.opponent(),
+                    rows,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use projectris::{
+    BoardSnapshot, CellValue, ClientMessage, Connection, GameStats, Player, Server, ServerMessage,
+    PROTOCOL_VERSION,
+};
+use std::{net::TcpListener, thread, time::Duration};
+
+const GRID_SIZE: [usize; 2] = [4, 8];
+
+fn start_server() -> (Server, String) {
+    let server = Server::new(TcpListener::bind("127.0.0.1:0").unwrap()).unwrap();
+    let address = server.local_addr().unwrap().to_string();
+
+    (server, address)
+}
+
+fn send(connection: &mut Connection, message: ClientMessage) {
+    connection.send(&message);
+    connection.flush().unwrap();
+}
+
+/// Polls the server until the client has a message, and returns it.
+fn next_message(server: &mut Server, connection: &mut Connection) -> ServerMessage {
+    for _ in 0..1000 {
+        server.poll().unwrap();
+        let mut messages = connection.receive::<ServerMessage>().unwrap();
+        if !messages.is_empty() {
+            assert_eq!(messages.len(), 1, "{:?}", messages);
+            return messages.remove(0);
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!("The server didn't send anything");
+}
+
+/// Connects two players and returns them, as `Player::One` and `Player::Two`, once their game has started.
+fn start_match(server: &mut Server, address: &str) -> (Connection, Connection) {
+    let mut one = Connection::connect(address).unwrap();
+    send(
+        &mut one,
+        ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            grid_size: GRID_SIZE,
+        },
+    );
+    // Polling in between makes sure the first one to say hello is the first player.
+    server.poll().unwrap();
+    let mut two = Connection::connect(address).unwrap();
+    send(
+        &mut two,
+        ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            grid_size: GRID_SIZE,
+        },
+    );
+
+    let ServerMessage::Start { player, seed } = next_message(server, &mut one) else {
+        panic!("The first player wasn't started");
+    };
+    assert_eq!(player, Player::One);
+    let ServerMessage::Start {
+        player: other_player,
+        seed: other_seed,
+    } = next_message(server, &mut two)
+    else {
+        panic!("The second player wasn't started");
+    };
+    assert_eq!(other_player, Player::Two);
+    assert_eq!(seed, other_seed);
+
+    (one, two)
+}
+
+fn snapshot() -> BoardSnapshot {
+    let cells = vec![CellValue::Empty; GRID_SIZE[0] * GRID_SIZE[1]];
+
+    BoardSnapshot {
+        left: cells.clone(),
+        right: cells,
+        stats: GameStats::default(),
+        incoming_garbage: 1,
+        piece: None,
+    }
+}
+
+#[test]
+fn boards_and_garbage_are_relayed_to_the_opponent() {
+    let (mut server, address) = start_server();
+    let (mut one, mut two) = start_match(&mut server, &address);
+
+    send(&mut one, ClientMessage::Board(snapshot()));
+    match next_message(&mut server, &mut two) {
+        ServerMessage::OpponentBoard(board) => assert_eq!(board, snapshot()),
+        message => panic!("Expected the opponent's board, got {:?}", message),
+    }
+
+    send(&mut two, ClientMessage::Garbage(3));
+    match next_message(&mut server, &mut one) {
+        ServerMessage::Garbage(rows) => assert_eq!(rows, 3),
+        message => panic!("Expected garbage, got {:?}", message),
+    }
+}
+
+#[test]
+fn losing_ends_the_game_for_both_players() {
+    let (mut server, address) = start_server();
+    let (mut one, mut two) = start_match(&mut server, &address);
+
+    send(&mut two, ClientMessage::Lost);
+    for connection in [&mut one, &mut two] {
+        match next_message(&mut server, connection) {
+            ServerMessage::End { winner } => assert_eq!(winner, Player::One),
+            message => panic!("Expected the end of the game, got {:?}", message),
+        }
+    }
+}
+
+#[test]
+fn leaving_is_reported_to_the_opponent() {
+    let (mut server, address) = start_server();
+    let (mut one, two) = start_match(&mut server, &address);
+
+    drop(two);
+    assert!(matches!(
+        next_message(&mut server, &mut one),
+        ServerMessage::OpponentLeft
+    ));
+}